@@ -0,0 +1,161 @@
+use crate::{source::TraceSource, AvgOpts, Break, Detrend, MergeOpts, PsdCascade, Trace};
+use std::ops::ControlFlow;
+
+/// Power spectral density of a trace
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spectrum {
+    /// Trace metadata
+    pub trace: Trace,
+    /// PSD values
+    pub psd: Vec<f32>,
+    /// Stage breaks
+    pub breaks: Vec<Break>,
+    /// Frequencies relative to the trace sample rate
+    pub frequencies: Vec<f32>,
+}
+
+/// PSD acquisition of all traces of a source
+///
+/// One [`PsdCascade`] per trace, created when the trace first appears
+/// and created again when the metadata of the trace changes.
+#[derive(Default)]
+pub struct Acquisition {
+    cascades: Vec<(Trace, PsdCascade<{ 1 << 9 }>)>,
+    detrend: Detrend,
+    avg: AvgOpts,
+}
+
+impl Acquisition {
+    /// Set the detrending method of all cascades
+    pub fn set_detrend(&mut self, detrend: Detrend) {
+        self.detrend = detrend;
+        for (_, c) in self.cascades.iter_mut() {
+            c.set_detrend(detrend);
+        }
+    }
+
+    /// Set the averaging options of all cascades
+    pub fn set_avg(&mut self, avg: AvgOpts) {
+        self.avg = avg;
+        for (_, c) in self.cascades.iter_mut() {
+            c.set_avg(avg);
+        }
+    }
+
+    /// Remove all cascades
+    pub fn reset(&mut self) {
+        self.cascades.clear();
+    }
+
+    /// Process a block of traces
+    ///
+    /// Cascades of traces that are no longer present are removed.
+    pub fn process(&mut self, traces: &[Trace]) {
+        self.cascades.truncate(traces.len());
+        for (i, trace) in traces.iter().enumerate() {
+            if !self
+                .cascades
+                .get(i)
+                .is_some_and(|(m, _)| m.same_meta(trace))
+            {
+                let mut c = PsdCascade::new(3);
+                c.set_detrend(self.detrend);
+                c.set_avg(self.avg);
+                let cascade = (trace.meta(), c);
+                if i < self.cascades.len() {
+                    self.cascades[i] = cascade;
+                } else {
+                    self.cascades.push(cascade);
+                }
+            }
+            self.cascades[i].1.process(&trace.data);
+        }
+    }
+
+    /// The spectra of all traces
+    pub fn spectra(&self, opts: &MergeOpts) -> Vec<Spectrum> {
+        self.cascades
+            .iter()
+            .map(|(trace, c)| {
+                let (psd, breaks) = c.psd(opts);
+                Spectrum {
+                    trace: trace.clone(),
+                    frequencies: Break::frequencies(&breaks),
+                    psd,
+                    breaks,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Acquire from any trace source
+///
+/// Each block of traces is processed and then passed to `control`
+/// (an empty slice if the source returned an error, which is logged).
+/// Acquisition stops when `control` breaks and the source is finished.
+///
+/// This is the acquisition loop of the `psd` and `stream_test` tools.
+/// Custom payload formats registered with [`crate::Registry::global()`] are decoded
+/// by the stream sources.
+pub fn run_acquisition<S: TraceSource>(
+    source: &mut S,
+    acq: &mut Acquisition,
+    mut control: impl FnMut(&mut Acquisition, &[Trace]) -> ControlFlow<()>,
+) {
+    let mut traces = vec![];
+    loop {
+        let block = match source.get(&mut traces) {
+            Ok(()) => {
+                acq.process(&traces);
+                &traces[..]
+            }
+            Err(e) => {
+                log::warn!("source: {}", e);
+                &[]
+            }
+        };
+        if control(acq, block).is_break() {
+            break;
+        }
+    }
+    source.finish();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::source::NoiseSource;
+
+    #[test]
+    fn acquire() {
+        let mut acq = Acquisition::default();
+        let mut blocks = 0;
+        run_acquisition(&mut NoiseSource::new(0), &mut acq, |_, traces| {
+            assert_eq!(traces.len(), 1);
+            blocks += 1;
+            if blocks < 100 {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        });
+        let spectra = acq.spectra(&MergeOpts::default());
+        assert_eq!(spectra.len(), 1);
+        assert_eq!(spectra[0].trace.name, "noise");
+        assert!(!spectra[0].psd.is_empty());
+
+        let mut traces: Vec<_> = (0..2)
+            .map(|i| Trace::new(format!("x{i}"), vec![1.0; 1 << 10]))
+            .collect();
+        acq.process(&traces);
+        traces[1] = traces[1].meta().with_divider(8);
+        traces[1].data = vec![1.0; 1 << 10];
+        acq.process(&traces[..]);
+        let spectra = acq.spectra(&MergeOpts::default());
+        assert_eq!(spectra[0].trace.name, "x0");
+        assert_eq!(spectra[1].trace.divider, 8);
+        acq.process(&traces[..1]);
+        assert_eq!(acq.spectra(&MergeOpts::default()).len(), 1);
+    }
+}
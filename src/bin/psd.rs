@@ -10,11 +10,16 @@ use egui_plot::{
     Bar, BarChart, GridInput, GridMark, Legend, Line, LineStyle, Plot, PlotPoint, PlotPoints, Text,
     VLine,
 };
-use std::{ops::RangeInclusive, sync::mpsc, time::Duration};
+use std::{
+    ops::{ControlFlow, RangeInclusive},
+    sync::mpsc,
+    time::Duration,
+};
 
 use stabilizer_stream::{
-    source::{Source, SourceOpts},
-    AvgOpts, Break, Detrend, MergeOpts, PsdCascade,
+    run_acquisition,
+    source::{Source, SourceOpts, TraceSource},
+    Acquisition, AvgOpts, Break, Detrend, MergeOpts, Spectrum,
 };
 
 #[derive(Parser, Debug)]
@@ -22,6 +27,13 @@ struct Opts {
     #[command(flatten)]
     source: SourceOpts,
 
+    /// Base sample rate in Hertz
+    ///
+    /// Defaults to the sample rate of the source if known (e.g. WAV files), else 1.
+    /// Traces with a sample rate divider are scaled accordingly.
+    #[arg(long)]
+    fs: Option<f32>,
+
     #[command(flatten)]
     acq: AcqOpts,
 }
@@ -32,8 +44,8 @@ struct AcqOpts {
     #[arg(short, long, default_value = "mean")]
     detrend: Detrend,
 
-    /// Sample rate in Hertz
-    #[arg(long, default_value_t = 1.0f32)]
+    /// Base sample rate in Hertz, see [`Opts::fs`]
+    #[arg(skip = 1.0)]
     fs: f32,
 
     /// Averaging limit
@@ -113,26 +125,29 @@ impl Trapezoidal {
     }
 }
 
-struct Trace {
+struct Plotted {
     name: String,
+    unit: &'static str,
+    fs: f32,
+    integral: f32,
+    plot: Vec<[f64; 2]>,
     breaks: Vec<Break>,
-    psd: Vec<f32>,
-    frequencies: Vec<f32>,
 }
 
-impl Trace {
-    fn into_plot(self, acq: &AcqOpts) -> (String, f32, Vec<[f64; 2]>, Vec<Break>) {
-        let logfs = acq.fs.log10();
+impl Plotted {
+    fn new(spectrum: Spectrum, acq: &AcqOpts) -> Self {
+        let fs = spectrum.trace.rate(acq.fs);
+        let logfs = fs.log10();
         let mut p0 = Trapezoidal::default();
         let mut pi = 0.0;
-        let plot = self
+        let plot = spectrum
             .psd
             .into_iter()
-            .zip(self.frequencies)
+            .zip(spectrum.frequencies)
             .filter_map(|(p, f)| {
                 // TODO: check at stage breaks
                 let dp = p0.push(f, p);
-                if (acq.integral_start..=acq.integral_end).contains(&(acq.fs * f)) {
+                if (acq.integral_start..=acq.integral_end).contains(&(fs * f)) {
                     // TODO: correctly interpolate at range limits
                     pi += dp;
                 }
@@ -150,73 +165,65 @@ impl Trace {
                 }
             })
             .collect();
-        (self.name, pi.sqrt(), plot, self.breaks)
+        Self {
+            name: spectrum.trace.name.into_owned(),
+            unit: spectrum.trace.unit,
+            fs,
+            integral: pi.sqrt(),
+            plot,
+            breaks: spectrum.breaks,
+        }
     }
 }
 
 fn main() -> Result<()> {
     env_logger::init();
-    let Opts { source, mut acq } = Opts::parse();
+    let Opts {
+        source,
+        fs,
+        mut acq,
+    } = Opts::parse();
+    let source = Source::new(source)?;
+    acq.fs = fs.or(source.sample_rate().map(|fs| fs as _)).unwrap_or(1.0);
     acq.integral_end *= acq.fs;
     acq.integral_start *= acq.fs;
+    run(source, acq)
+}
 
+/// Run the PSD acquisition and GUI
+fn run(mut source: Source, acq: AcqOpts) -> Result<()> {
     let (cmd_send, cmd_recv) = mpsc::channel();
     let (trace_send, trace_recv) = mpsc::sync_channel(1);
-    let mut source = Source::new(source)?;
-    let mut dec = vec![];
 
     let receiver = std::thread::spawn(move || -> Result<()> {
-        loop {
-            match source.get() {
-                Ok(traces) => {
-                    for (i, (name, trace)) in traces.iter().enumerate() {
-                        if dec.len() <= i {
-                            let mut p = PsdCascade::<{ 1 << 9 }>::new(3);
-                            p.set_detrend(acq.detrend);
-                            p.set_avg(acq.avg_opts());
-                            dec.push((*name, p));
-                        }
-                        dec[i].1.process(trace);
-                    }
-                }
-                Err(e) => log::warn!("source: {}", e),
-            }
-
+        let mut opts = acq;
+        let mut acq = Acquisition::default();
+        acq.set_detrend(opts.detrend);
+        acq.set_avg(opts.avg_opts());
+        let mut ret = Ok(());
+        run_acquisition(&mut source, &mut acq, |acq, _| {
             match cmd_recv.try_recv() {
                 Err(mpsc::TryRecvError::Empty) => {}
-                Err(mpsc::TryRecvError::Disconnected) | Ok(Cmd::Exit) => break,
-                Ok(Cmd::Reset) => dec.clear(),
-                Ok(Cmd::Send(opts)) => {
-                    acq = opts;
-                    for dec in dec.iter_mut() {
-                        dec.1.set_detrend(acq.detrend);
-                        dec.1.set_avg(acq.avg_opts());
-                    }
-                    let merge_opts = acq.merge_opts();
-                    let trace = dec
-                        .iter()
-                        .map(|(name, dec)| {
-                            let (psd, breaks) = dec.psd(&merge_opts);
-                            let frequencies = Break::frequencies(&breaks);
-                            Trace {
-                                name: name.to_string(),
-                                breaks,
-                                psd,
-                                frequencies,
-                            }
-                        })
-                        .collect();
-                    match trace_send.try_send(trace) {
-                        Ok(()) => {}
-                        Err(mpsc::TrySendError::Full(_)) => {}
-                        Err(e) => Err(e)?,
+                Err(mpsc::TryRecvError::Disconnected) | Ok(Cmd::Exit) => {
+                    return ControlFlow::Break(());
+                }
+                Ok(Cmd::Reset) => acq.reset(),
+                Ok(Cmd::Send(o)) => {
+                    opts = o;
+                    acq.set_detrend(opts.detrend);
+                    acq.set_avg(opts.avg_opts());
+                    match trace_send.try_send(acq.spectra(&opts.merge_opts())) {
+                        Ok(()) | Err(mpsc::TrySendError::Full(_)) => {}
+                        Err(e) => {
+                            ret = Err(e.into());
+                            return ControlFlow::Break(());
+                        }
                     }
                 }
-            };
-        }
-
-        source.finish();
-        Ok(())
+            }
+            ControlFlow::Continue(())
+        });
+        ret
     });
 
     let options = eframe::NativeOptions {
@@ -239,9 +246,9 @@ fn main() -> Result<()> {
 }
 
 struct App {
-    trace_recv: mpsc::Receiver<Vec<Trace>>,
+    trace_recv: mpsc::Receiver<Vec<Spectrum>>,
     cmd_send: mpsc::Sender<Cmd>,
-    current: Vec<(String, Vec<[f64; 2]>, Vec<Break>)>,
+    current: Vec<Plotted>,
     acq: AcqOpts,
     repaint: f32,
     hold: bool,
@@ -249,7 +256,7 @@ struct App {
 
 impl App {
     fn new(
-        trace_recv: mpsc::Receiver<Vec<Trace>>,
+        trace_recv: mpsc::Receiver<Vec<Spectrum>>,
         cmd_send: mpsc::Sender<Cmd>,
         acq: AcqOpts,
     ) -> Self {
@@ -276,10 +283,7 @@ impl eframe::App for App {
                 if !self.hold {
                     self.current = trace
                         .into_iter()
-                        .map(|t| {
-                            let (name, pi, xy, b) = t.into_plot(&self.acq);
-                            (format!("{name}: {pi:.2e}"), xy, b)
-                        })
+                        .map(|t| Plotted::new(t, &self.acq))
                         .collect();
                     ctx.request_repaint_after(Duration::from_secs_f32(self.repaint));
                 }
@@ -319,9 +323,9 @@ impl App {
             .allow_zoom(false)
             .height(20.0)
             .show(ui, |plot_ui| {
-                let ldfs = self.acq.fs.log10() as f64;
                 // TODO: single-trace data
-                for (_name, _line, breaks) in self.current.iter().take(1) {
+                for Plotted { fs, breaks, .. } in self.current.iter().take(1) {
+                    let ldfs = fs.log10() as f64;
                     let mut end = f32::NEG_INFINITY;
                     let mut texts = Vec::with_capacity(breaks.len());
                     plot_ui.bar_chart(BarChart::new(
@@ -341,7 +345,7 @@ impl App {
                                         "{}.{:01}, {:.1e} Hz",
                                         b.count,
                                         (buf * 10.0) as i32,
-                                        self.acq.fs * rbw
+                                        fs * rbw
                                     ),
                                 ));
                                 Bar::new(
@@ -369,6 +373,16 @@ impl App {
                 }
             });
 
+        // Label the axis with the unit if all traces agree
+        let unit = match self.current.first() {
+            Some(p) if self.current.iter().all(|q| q.unit == p.unit) => p.unit,
+            _ => "",
+        };
+        let y_label = if unit.is_empty() {
+            "Power spectral density (dB/Hz) or integrated RMS".to_string()
+        } else {
+            format!("Power spectral density (dB {unit}²/Hz) or integrated RMS ({unit})")
+        };
         Plot::new("plot")
             .x_axis_label("Modulation frequency (Hz)")
             .x_grid_spacer(log10_grid_spacer)
@@ -376,7 +390,7 @@ impl App {
             .link_axis("plots", false, false)
             .auto_bounds([true; 2].into())
             .y_axis_min_width(4.0)
-            .y_axis_label("Power spectral density (dB/Hz) or integrated RMS")
+            .y_axis_label(y_label)
             .legend(Legend::default())
             .label_formatter(log10x_formatter)
             .show(ui, |plot_ui| {
@@ -390,8 +404,11 @@ impl App {
                         .stroke((1.0, Color32::DARK_GRAY))
                         .style(LineStyle::dashed_loose()),
                 );
-                for (name, trace, _) in self.current.iter() {
-                    plot_ui.line(Line::new(PlotPoints::from(trace.clone())).name(name));
+                for p in self.current.iter() {
+                    plot_ui.line(
+                        Line::new(PlotPoints::from(p.plot.clone()))
+                            .name(format!("{}: {:.2e} {}", p.name, p.integral, p.unit)),
+                    );
                 }
             });
     }
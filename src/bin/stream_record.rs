@@ -0,0 +1,136 @@
+use anyhow::Result;
+use clap::Parser;
+use std::{
+    fs::File,
+    io::BufWriter,
+    net::{Ipv4Addr, SocketAddr},
+    time::{Duration, Instant, SystemTime},
+};
+
+use stabilizer_stream::{
+    pcap::PcapWriter,
+    record::{Recorder, Rotation},
+    source::{TraceSource, UdpSource},
+    CaptureHeader,
+};
+
+/// Record complete Stabilizer stream frames to disk.
+///
+/// The files can be replayed with `--file`.
+/// With `--pcap`, a pcap file is written for use with other network tools.
+/// Use `RUST_LOG=info cargo run` to see the loss summary.
+#[derive(Parser, Debug)]
+struct Opts {
+    /// The local IP to receive streaming data on.
+    #[arg(short, long, default_value = "0.0.0.0")]
+    ip: std::net::Ipv4Addr,
+
+    /// The UDP port to receive streaming data on.
+    #[arg(short, long, default_value_t = 9293)]
+    port: u16,
+
+    /// Output file
+    #[arg(short, long, default_value = "stream.bin")]
+    output: String,
+
+    /// Stop after this many seconds
+    #[arg(short, long)]
+    duration: Option<f32>,
+
+    /// Start a new file after this many bytes
+    #[arg(long, conflicts_with = "pcap")]
+    rotate_size: Option<u64>,
+
+    /// Start a new file after this many seconds
+    #[arg(long, conflicts_with = "pcap")]
+    rotate_time: Option<f32>,
+
+    /// Write a pcap file with the received UDP datagrams
+    #[arg(long)]
+    pcap: bool,
+
+    /// Write headerless frame dumps (replay with `--frame-size`)
+    #[arg(long)]
+    no_header: bool,
+
+    /// Base sample rate in Hertz to note in the capture header
+    #[arg(long)]
+    fs: Option<f64>,
+
+    /// Notes for the capture header
+    #[arg(long, default_value = "")]
+    notes: String,
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let Opts {
+        ip,
+        port,
+        output,
+        duration,
+        rotate_size,
+        rotate_time,
+        pcap,
+        no_header,
+        fs,
+        notes,
+    } = Opts::parse();
+
+    let mut source = UdpSource::new(ip, port)?;
+    let local = source
+        .local_addr()
+        .unwrap_or(SocketAddr::new(ip.into(), port));
+    let mut pcap = if pcap {
+        Some(PcapWriter::new(BufWriter::new(File::create(&output)?))?)
+    } else {
+        None
+    };
+    let mut recorder = Recorder::new(
+        output,
+        Rotation {
+            size: rotate_size,
+            time: rotate_time.map(Duration::from_secs_f32),
+        },
+    );
+    if !no_header {
+        recorder.set_header(Some(CaptureHeader {
+            sample_rate: fs,
+            notes,
+            ..Default::default()
+        }));
+    }
+    let duration = duration.map(Duration::from_secs_f32);
+    let start = Instant::now();
+
+    let mut buf = [0u8; 2048];
+    while duration.map_or(true, |d| start.elapsed() < d) {
+        match source.recv(&mut buf) {
+            Ok((_header, frame)) => {
+                if let Some(pcap) = &mut pcap {
+                    let sender = source
+                        .sender()
+                        .unwrap_or(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0));
+                    let timestamp = source.timestamp().unwrap_or_else(SystemTime::now);
+                    pcap.write_udp(timestamp, sender, local, frame)?;
+                    continue;
+                }
+                if let Some(header) = recorder.header_mut() {
+                    header.source = source.sender();
+                    header.start = source.timestamp();
+                }
+                recorder.write(frame)?;
+            }
+            Err(e) => log::warn!("source: {}", e),
+        }
+    }
+    if let Some(mut pcap) = pcap {
+        pcap.flush()?;
+    } else {
+        recorder.close()?;
+        log::info!("Wrote {} files", recorder.files());
+    }
+    source.finish();
+
+    Ok(())
+}
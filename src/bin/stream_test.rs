@@ -1,11 +1,13 @@
 use anyhow::Result;
 use clap::Parser;
+use std::ops::ControlFlow;
 use std::sync::mpsc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use stabilizer_stream::{
+    run_acquisition,
     source::{Source, SourceOpts},
-    Break, Detrend, MergeOpts, PsdCascade, VarBuilder,
+    Acquisition, Break, Detrend, MergeOpts, Spectrum, VarBuilder,
 };
 
 /// Execute stabilizer stream throughput testing.
@@ -29,32 +31,43 @@ fn main() -> Result<()> {
         duration,
         trace,
     } = Opts::parse();
+    run(Source::new(source)?, duration, trace)
+}
+
+/// Run the throughput test
+fn run(mut source: Source, duration: f32, trace: usize) -> Result<()> {
     let merge_opts = MergeOpts::default();
 
     let (cmd_send, cmd_recv) = mpsc::channel();
     let receiver = std::thread::spawn(move || {
-        let mut source = Source::new(source)?;
+        let mut acq = Acquisition::default();
+        acq.set_detrend(Detrend::Midpoint);
 
-        let mut dec: Vec<_> = (0..4)
-            .map(|_| {
-                let mut c = PsdCascade::<{ 1 << 9 }>::new(3);
-                c.set_detrend(Detrend::Midpoint);
-                c
-            })
-            .collect();
+        let mut samples = 0;
+        let start = Instant::now();
+        run_acquisition(&mut source, &mut acq, |_, traces| {
+            samples += traces.get(trace).map_or(0, |t| t.data.len());
+            if cmd_recv.try_recv() == Err(mpsc::TryRecvError::Empty) {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        });
 
-        while cmd_recv.try_recv() == Err(mpsc::TryRecvError::Empty) {
-            match source.get() {
-                Ok(traces) => {
-                    for (dec, x) in dec.iter_mut().zip(traces) {
-                        dec.process(&x.1);
-                    }
-                }
-                Err(e) => log::warn!("{e}"),
-            };
-        }
+        let elapsed = start.elapsed().as_secs_f64();
+        log::info!(
+            "Throughput: {} samples in {:.1} s ({:.3} MS/s)",
+            samples,
+            elapsed,
+            samples as f64 / elapsed * 1e-6
+        );
 
-        let (y, b) = dec[trace].psd(&merge_opts);
+        let Some(Spectrum {
+            psd: y, breaks: b, ..
+        }) = acq.spectra(&merge_opts).into_iter().nth(trace)
+        else {
+            anyhow::bail!("No trace {trace}");
+        };
         log::info!("breaks: {:?}", b);
         log::info!("psd: {:?}", y);
 
@@ -70,8 +83,6 @@ fn main() -> Result<()> {
             log::info!("fdev: {:?}", fdev);
         }
 
-        source.finish();
-
         Result::<()>::Ok(())
     });
 
@@ -1,9 +1,13 @@
 use anyhow::Result;
 use clap::Parser;
-use std::io::Write;
+use std::io::{ErrorKind, Write};
 
-use stabilizer_stream::source::{Source, SourceOpts};
+use stabilizer_stream::{
+    source::{is_terminal, Source, SourceOpts, TraceSource},
+    Trace,
+};
 
+/// Write a trace as raw little endian f32 to stdout or as a WAV file
 #[derive(Parser, Debug)]
 struct Opts {
     #[command(flatten)]
@@ -11,19 +15,114 @@ struct Opts {
 
     #[arg(short, long, default_value_t = 0)]
     trace: usize,
+
+    /// Write a mono 32 bit float WAV file instead
+    #[arg(long)]
+    wav: Option<String>,
+
+    /// WAV sample rate in Hertz
+    ///
+    /// Defaults to the sample rate of the trace if known, else 48 kHz.
+    #[arg(long)]
+    fs: Option<u32>,
+
+    /// Stop after this many samples of the trace
+    ///
+    /// Default: until the end of the source
+    #[arg(long)]
+    samples: Option<u64>,
+}
+
+/// Get the next block of traces and select a trace
+///
+/// Returns `None` at the end of the source. Non-terminal source errors are logged and skipped.
+fn next<'a>(
+    source: &mut Source,
+    traces: &'a mut Vec<Trace>,
+    trace: usize,
+) -> Result<Option<&'a Trace>> {
+    loop {
+        match source.get(traces) {
+            Ok(()) => break,
+            Err(e)
+                if e.downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == ErrorKind::UnexpectedEof) =>
+            {
+                return Ok(None)
+            }
+            Err(e) if is_terminal(&e) => return Err(e),
+            Err(e) => log::warn!("source: {}", e),
+        }
+    }
+    let n = traces.len();
+    let t = traces
+        .get(trace)
+        .ok_or_else(|| anyhow::anyhow!("Trace {} out of range ({} traces)", trace, n))?;
+    Ok(Some(t))
 }
 
 fn main() -> Result<()> {
     env_logger::init();
-    let Opts { trace, source } = Opts::parse();
+    let Opts {
+        trace,
+        source,
+        wav,
+        fs,
+        samples,
+    } = Opts::parse();
 
     let mut source = Source::new(source)?;
-    let mut stdout = std::io::BufWriter::new(std::io::stdout());
 
-    loop {
-        let t = &source.get()?[trace].1;
-        stdout.write_all(bytemuck::cast_slice(&t[..]))?;
+    let mut traces = vec![];
+    let mut remaining = samples.unwrap_or(u64::MAX);
+    if let Some(path) = wav {
+        let mut writer = None;
+        // Finalize the WAV header at the end of the source, at the sample limit or on error
+        let ret = loop {
+            if remaining == 0 {
+                break Ok(());
+            }
+            let t = match next(&mut source, &mut traces, trace) {
+                Ok(Some(t)) => t,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            let writer = match &mut writer {
+                Some(writer) => writer,
+                None => {
+                    let spec = hound::WavSpec {
+                        channels: 1,
+                        sample_rate: fs
+                            .or(source.sample_rate().map(|fs| t.rate(fs as _).round() as _))
+                            .unwrap_or(48000),
+                        bits_per_sample: 32,
+                        sample_format: hound::SampleFormat::Float,
+                    };
+                    writer.insert(hound::WavWriter::create(&path, spec)?)
+                }
+            };
+            for x in t.data.iter().take(remaining.min(usize::MAX as _) as _) {
+                writer.write_sample(*x)?;
+                remaining -= 1;
+            }
+        };
+        if let Some(writer) = writer {
+            writer.finalize()?;
+        }
+        source.finish();
+        return ret;
     }
 
-    // source.finish();
+    let mut stdout = std::io::BufWriter::new(std::io::stdout());
+    while remaining > 0 {
+        let Some(t) = next(&mut source, &mut traces, trace)? else {
+            break;
+        };
+        let t = &t.data[..t.data.len().min(remaining.min(usize::MAX as _) as _)];
+        stdout.write_all(bytemuck::cast_slice(t))?;
+        remaining -= t.len() as u64;
+    }
+    stdout.flush()?;
+    source.finish();
+    Ok(())
 }
@@ -0,0 +1,173 @@
+use crate::Format;
+use std::{
+    io::{BufRead, Error, ErrorKind, Read, Result},
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+/// Capture file header
+///
+/// Describes the stream frames that follow it in a capture file.
+///
+/// Layout (little endian):
+/// * magic `b"SSCF"`
+/// * `u16` version
+/// * `u32` total header length in bytes, including magic, version and length
+/// * `u8` format ID, zero if unknown
+/// * `u32` frame size
+/// * `f64` base sample rate in Hertz, zero if unknown
+/// * `u64` start time in nanoseconds since the UNIX epoch, zero if unknown
+/// * `u8` length and UTF-8 source address, empty if unknown
+/// * `u32` length and UTF-8 notes
+///
+/// Later versions only append fields. Readers skip to the header length.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CaptureHeader {
+    /// Stream format of the frames
+    pub format: Option<Format>,
+    /// Size of each frame in bytes
+    pub frame_size: usize,
+    /// Base sample rate in Hertz
+    pub sample_rate: Option<f64>,
+    /// Time of the first frame
+    pub start: Option<SystemTime>,
+    /// Address of the sending device
+    pub source: Option<SocketAddr>,
+    /// Free-form notes
+    pub notes: String,
+}
+
+impl CaptureHeader {
+    /// Magic bytes at the start of a capture file
+    pub const MAGIC: [u8; 4] = *b"SSCF";
+
+    /// Current header version
+    pub const VERSION: u16 = 1;
+
+    /// Largest header length in bytes accepted by [`CaptureHeader::read()`]
+    pub const MAX_SIZE: usize = 1 << 20;
+
+    // Magic, version and length
+    const PREAMBLE: usize = 10;
+
+    /// Serialize the header
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(64 + self.notes.len());
+        buf.extend_from_slice(&Self::MAGIC);
+        buf.extend_from_slice(&Self::VERSION.to_le_bytes());
+        buf.extend_from_slice(&[0; 4]); // length
+        buf.push(self.format.map(u8::from).unwrap_or(0));
+        buf.extend_from_slice(&(self.frame_size as u32).to_le_bytes());
+        buf.extend_from_slice(&self.sample_rate.unwrap_or(0.0).to_le_bytes());
+        let start = self
+            .start
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|t| t.as_nanos() as u64)
+            .unwrap_or(0);
+        buf.extend_from_slice(&start.to_le_bytes());
+        let source = self.source.map(|s| s.to_string()).unwrap_or_default();
+        buf.push(source.len() as u8);
+        buf.extend_from_slice(source.as_bytes());
+        buf.extend_from_slice(&(self.notes.len() as u32).to_le_bytes());
+        buf.extend_from_slice(self.notes.as_bytes());
+        let len = buf.len() as u32;
+        buf[6..10].copy_from_slice(&len.to_le_bytes());
+        buf
+    }
+
+    /// Read a header
+    pub fn read(reader: &mut impl Read) -> Result<Self> {
+        let invalid = |msg| Error::new(ErrorKind::InvalidData, msg);
+        let mut pre = [0; Self::PREAMBLE];
+        reader.read_exact(&mut pre)?;
+        if pre[..4] != Self::MAGIC {
+            return Err(invalid("Invalid capture magic"));
+        }
+        let version = u16::from_le_bytes([pre[4], pre[5]]);
+        if version == 0 {
+            return Err(invalid("Invalid capture version"));
+        }
+        let len = u32::from_le_bytes(pre[6..].try_into().unwrap()) as usize;
+        if len > Self::MAX_SIZE {
+            return Err(invalid("Capture header too long"));
+        }
+        let mut body = vec![0; len.saturating_sub(Self::PREAMBLE)];
+        reader.read_exact(&mut body)?;
+
+        let mut body = &body[..];
+        let mut take = |n: usize| -> Result<&[u8]> {
+            if body.len() < n {
+                return Err(invalid("Truncated capture header"));
+            }
+            let (head, tail) = body.split_at(n);
+            body = tail;
+            Ok(head)
+        };
+        let format = match take(1)?[0] {
+            0 => None,
+            f => Some(Format::from(f)),
+        };
+        let frame_size = u32::from_le_bytes(take(4)?.try_into().unwrap()) as _;
+        let sample_rate = f64::from_le_bytes(take(8)?.try_into().unwrap());
+        let start = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let n = take(1)?[0] as usize;
+        let source = core::str::from_utf8(take(n)?)
+            .map_err(|_| invalid("Invalid capture source address"))?;
+        let n = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let notes = core::str::from_utf8(take(n)?)
+            .map_err(|_| invalid("Invalid capture notes"))?
+            .to_string();
+        Ok(Self {
+            format,
+            frame_size,
+            sample_rate: (sample_rate > 0.0).then_some(sample_rate),
+            start: (start > 0).then(|| SystemTime::UNIX_EPOCH + Duration::from_nanos(start)),
+            source: source.parse().ok(),
+            notes,
+        })
+    }
+
+    /// Read a header if the reader is positioned at one
+    ///
+    /// Returns `None` and consumes nothing for headerless frame dumps.
+    pub fn detect(reader: &mut impl BufRead) -> Result<Option<Self>> {
+        if reader.fill_buf()?.starts_with(&Self::MAGIC) {
+            Self::read(reader).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let header = CaptureHeader {
+            format: Some(Format::AdcDac),
+            frame_size: 1032,
+            sample_rate: Some(100e6 / 128.0),
+            start: Some(SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123)),
+            source: Some("10.0.0.2:9293".parse().unwrap()),
+            notes: "loop closed".into(),
+        };
+        let mut buf = header.to_bytes();
+        // Unknown trailing fields of a later version
+        buf.extend_from_slice(&[1, 2, 3]);
+        let len = buf.len() as u32;
+        buf[6..10].copy_from_slice(&len.to_le_bytes());
+        buf.extend_from_slice(&crate::MAGIC_WORD);
+        let mut reader = &buf[..];
+        assert_eq!(CaptureHeader::detect(&mut reader).unwrap(), Some(header));
+        assert_eq!(reader, crate::MAGIC_WORD);
+        assert_eq!(CaptureHeader::detect(&mut reader).unwrap(), None);
+        assert_eq!(reader, crate::MAGIC_WORD);
+
+        buf[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+        let e = CaptureHeader::read(&mut &buf[..]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,402 @@
+use super::{FlsChannel, Format, Frame, Header};
+
+/// Stream frame payload builder
+///
+/// Accumulates batches in the wire format of a stream format and
+/// serializes them into stream frames.
+pub trait PayloadBuilder {
+    /// The stream format of the payload
+    fn format(&self) -> Format;
+
+    /// Number of batches in the payload
+    fn batches(&self) -> usize;
+
+    /// The payload in wire format
+    fn as_bytes(&self) -> &[u8];
+
+    /// The frame header for the current payload.
+    fn header(&self, seq: u32) -> Header {
+        Header {
+            format: self.format(),
+            batches: self.batches() as _,
+            seq,
+        }
+    }
+
+    /// Serialize the payload into a stream frame.
+    ///
+    /// # Args
+    /// * `seq`: Sequence number of the first batch
+    fn frame(&self, seq: u32) -> Vec<u8> {
+        Frame::encode(&self.header(seq), self.as_bytes())
+    }
+}
+
+/// Builder for [`super::AdcDac`] payloads
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdcDacBuilder {
+    data: Vec<u8>,
+    batches: usize,
+}
+
+impl AdcDacBuilder {
+    /// Append a batch of raw ADC and DAC codes.
+    ///
+    /// DAC codes are offset binary as sent by Stabilizer.
+    /// All batches must have the same size `N`.
+    pub fn push<const N: usize>(&mut self, adc: [[i16; N]; 2], dac: [[u16; N]; 2]) -> &mut Self {
+        assert!(self.batches < u8::MAX as usize);
+        assert!(N > 0 && self.data.len() == self.batches * N * 4 * 2);
+        for ch in adc.iter() {
+            self.data.extend(ch.iter().flat_map(|v| v.to_le_bytes()));
+        }
+        for ch in dac.iter() {
+            self.data.extend(ch.iter().flat_map(|v| v.to_le_bytes()));
+        }
+        self.batches += 1;
+        self
+    }
+}
+
+impl PayloadBuilder for AdcDacBuilder {
+    fn format(&self) -> Format {
+        Format::AdcDac
+    }
+
+    fn batches(&self) -> usize {
+        self.batches
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Builder for [`super::Fls`] payloads
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FlsBuilder {
+    data: Vec<u8>,
+    batches: usize,
+}
+
+impl FlsBuilder {
+    /// Append a batch of data for both channels.
+    pub fn push(&mut self, batch: [FlsChannel; 2]) -> &mut Self {
+        assert!(self.batches < u8::MAX as usize);
+        for ch in batch.iter() {
+            self.data.extend(ch.to_words().iter().flatten());
+        }
+        self.batches += 1;
+        self
+    }
+}
+
+impl PayloadBuilder for FlsBuilder {
+    fn format(&self) -> Format {
+        Format::Fls
+    }
+
+    fn batches(&self) -> usize {
+        self.batches
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Builder for [`super::ThermostatEem`] payloads
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ThermostatEemBuilder {
+    data: Vec<u8>,
+    batches: usize,
+}
+
+impl ThermostatEemBuilder {
+    /// Append a batch of channel values.
+    pub fn push(&mut self, batch: [f32; 16 + 4]) -> &mut Self {
+        assert!(self.batches < u8::MAX as usize);
+        self.data.extend(batch.iter().flat_map(|v| v.to_le_bytes()));
+        self.batches += 1;
+        self
+    }
+}
+
+impl PayloadBuilder for ThermostatEemBuilder {
+    fn format(&self) -> Format {
+        Format::ThermostatEem
+    }
+
+    fn batches(&self) -> usize {
+        self.batches
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Builder for [`super::Lockin`] payloads
+#[derive(Clone, Debug, PartialEq)]
+pub struct LockinBuilder {
+    format: Format,
+    data: Vec<u8>,
+    batches: usize,
+}
+
+impl LockinBuilder {
+    /// Build payloads with the given format ID
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            data: vec![],
+            batches: 0,
+        }
+    }
+
+    /// Append a batch with demodulated in-phase and quadrature components.
+    pub fn push(&mut self, iq: [i32; 2]) -> &mut Self {
+        assert!(self.batches < u8::MAX as usize);
+        self.data.extend(iq.iter().flat_map(|v| v.to_le_bytes()));
+        self.batches += 1;
+        self
+    }
+}
+
+impl PayloadBuilder for LockinBuilder {
+    fn format(&self) -> Format {
+        self.format
+    }
+
+    fn batches(&self) -> usize {
+        self.batches
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{de::Error, Fls, FlsPhase, Lockin, Payload, Registry};
+
+    #[test]
+    fn adc_dac() {
+        let mut b = AdcDacBuilder::default();
+        for i in 0..3 {
+            b.push(
+                [[i * 100 - 7; 8], [-i; 8]],
+                [[0x8000 + i as u16; 8], [0x7fff; 8]],
+            );
+        }
+        let buf = b.frame(0x1234_5678);
+        let frame = Frame::from_bytes(&buf).unwrap();
+        assert_eq!(
+            frame.header,
+            Header {
+                format: Format::AdcDac,
+                batches: 3,
+                seq: 0x1234_5678
+            }
+        );
+        assert_eq!(frame.to_bytes(), buf);
+        let traces = frame.payload.traces().unwrap();
+        let names: Vec<_> = traces.iter().map(|t| &t.name).collect();
+        assert_eq!(names, ["ADC0", "ADC1", "DAC0", "DAC1"]);
+        let lsb = 5.0 / 2.0 * 4.096 / (1u16 << 15) as f32;
+        assert_eq!(traces[0].unit, "V");
+        assert_eq!(traces[0].scale, lsb);
+        assert_eq!(traces[0].divider, 1);
+        assert_eq!(traces[0].data.len(), 3 * 8);
+        assert_eq!(traces[0].data[8], 93.0 * lsb);
+        assert_eq!(traces[1].data[23], -2.0 * lsb);
+        assert_eq!(traces[2].data[16], 2.0 * lsb);
+        assert_eq!(traces[3].data[0], -lsb);
+        let mut t = vec![];
+        assert_eq!(
+            Frame::decode_into(&buf, &mut t, false).unwrap(),
+            frame.header
+        );
+        assert_eq!(t, traces);
+
+        let (_, data) = Frame::split(&buf).unwrap();
+        let p = crate::AdcDac::new(3, data).unwrap();
+        assert!(p.adc(0).skip(16).all(|v| v == 193));
+        assert!(p.adc(1).skip(8).take(8).all(|v| v == -1));
+        assert!(p.dac(0).take(8).all(|v| v == 0x8000));
+        assert!(p.dac(1).all(|v| v == 0x7fff));
+    }
+
+    #[test]
+    fn fls() {
+        let mut b = FlsBuilder::default();
+        let a = FlsChannel {
+            demod: [3, 4],
+            phase: 1 << 15,
+            ftw: 1 << 30,
+            pow_amp: 0x8000_4000,
+            pll: -9,
+        };
+        let mut c = FlsChannel {
+            demod: [-5, 6],
+            phase: -(3 << 40),
+            ..Default::default()
+        };
+        b.push([a, c]);
+        c.phase += 1 << 16;
+        b.push([a, c]);
+        let buf = b.frame(7);
+        let frame = Frame::from_bytes(&buf).unwrap();
+        assert_eq!(frame.header, b.header(7));
+        assert_eq!(frame.to_bytes(), buf);
+        let traces = frame.payload.traces().unwrap();
+        let names: Vec<_> = traces.iter().map(|t| &t.name).collect();
+        assert_eq!(names, Fls::NAMES);
+        let x = 1.0 / i32::MAX as f32;
+        let pi = core::f32::consts::PI;
+        assert_eq!(traces[0].data, [5.0 * x; 2]);
+        assert_eq!(traces[1].data, [pi; 2]);
+        assert_eq!(traces[1].unit, "rad");
+        assert_eq!(traces[1].divider, 8);
+        assert_eq!(traces[2].data, [-5.0 * x; 2]);
+        assert_eq!(traces[3].data, [6.0 * x; 2]);
+        assert_eq!(traces[4].data, [3.0 * x; 2]);
+        assert_eq!(traces[6].data, [0.25; 2]);
+        assert_eq!(traces[7].data, [pi * 0.5; 2]);
+        assert_eq!(traces[8].data, [0.5; 2]);
+        assert_eq!(traces[9].data, [-9.0; 2]);
+
+        let (_, data) = Frame::split(&buf).unwrap();
+        let p = Fls::new(2, data).unwrap();
+        assert_eq!(p.channel(0).collect::<Vec<_>>(), [a; 2]);
+        assert_eq!(p.channel(1).last(), Some(c));
+        let mut phase = [FlsPhase::new(1.0, true); 2];
+        let mut t = vec![];
+        p.traces_with(&mut phase, &mut t, false).unwrap();
+        assert_eq!(t[11].data, [0.0, core::f32::consts::TAU * 65536.0]);
+        assert_eq!(t[11].scale, core::f32::consts::TAU);
+        p.traces_with(&mut phase, &mut t, true).unwrap();
+        assert_eq!(t[11].data[2], 0.0);
+        let t = Fls::with_batch_size(2, 4, data).unwrap().traces().unwrap();
+        assert_eq!(t[11].divider, 4);
+        let t = p.with_phase(FlsPhase::new(1.0, false)).traces().unwrap();
+        assert_eq!(t[11].scale, core::f32::consts::TAU);
+
+        let mut phase = FlsPhase::new(1.0, true);
+        assert_eq!(phase.update(i64::MAX - 1), 0.0);
+        assert_eq!(phase.update(i64::MIN + 1), 3.0 * core::f32::consts::TAU);
+        assert_eq!(phase.update(i64::MAX), 1.0 * core::f32::consts::TAU);
+        phase.reset();
+        assert_eq!(phase.update(5), 0.0);
+    }
+
+    #[test]
+    fn thermostat_eem() {
+        let mut b = ThermostatEemBuilder::default();
+        let mut batch = [0.0; 20];
+        for (i, v) in batch.iter_mut().enumerate() {
+            *v = i as f32 * 0.5;
+        }
+        b.push(batch).push(batch);
+        let buf = b.frame(u32::MAX);
+        let frame = Frame::from_bytes(&buf).unwrap();
+        assert_eq!(frame.header, b.header(u32::MAX));
+        assert_eq!(frame.to_bytes(), buf);
+        let traces = frame.payload.traces().unwrap();
+        assert_eq!(traces.len(), 20);
+        assert_eq!(traces[8].name, "T20");
+        assert_eq!(traces[8].unit, "°C");
+        assert_eq!(traces[8].data, [4.0, 4.0]);
+        assert_eq!(traces[16].name, "I0");
+        assert_eq!(traces[16].unit, "A");
+        assert_eq!(traces[16].data, [8.0, 8.0]);
+    }
+
+    #[test]
+    fn lockin() {
+        let format = Format::Other(0x84);
+        let mut b = LockinBuilder::new(format);
+        b.push([3, 4]).push([0, -7]);
+        let buf = b.frame(3);
+        let mut registry = Registry::default();
+        assert!(Frame::from_bytes_with(&buf, &registry).is_err());
+        registry.register(format, |batches, data| {
+            Ok(Box::new(Lockin::with_batch_size(batches, 16, data)?))
+        });
+        let frame = Frame::from_bytes_with(&buf, &registry).unwrap();
+        assert_eq!(frame.header, b.header(3));
+        assert_eq!(frame.header.format, format);
+        assert_eq!(frame.to_bytes(), buf);
+        let traces = frame.payload.traces().unwrap();
+        assert_eq!(traces[0].divider, 16);
+        let names: Vec<_> = traces.iter().map(|t| &t.name).collect();
+        assert_eq!(names, ["I", "Q", "R", "P"]);
+        let x = 1.0 / i32::MAX as f32;
+        assert_eq!(traces[0].data, [3.0 * x, 0.0]);
+        assert_eq!(traces[1].data, [4.0 * x, -7.0 * x]);
+        assert_eq!(traces[2].data, [5.0 * x, 7.0 * x]);
+        assert_eq!(
+            traces[3].data,
+            [4f32.atan2(3.0), -core::f32::consts::FRAC_PI_2]
+        );
+        assert_eq!(traces[3].unit, "rad");
+    }
+
+    #[test]
+    fn adc_dac_batch_size() {
+        for batch_size in 1..=32 {
+            for batches in [1, 3] {
+                let mut data = vec![];
+                for b in 0..batches {
+                    for ch in 0..4 {
+                        for i in 0..batch_size {
+                            data.extend((((b * batch_size + i) * 4 + ch) as u16).to_le_bytes());
+                        }
+                    }
+                }
+                let frame = Frame::encode(
+                    &Header {
+                        format: Format::AdcDac,
+                        batches: batches as _,
+                        seq: 0,
+                    },
+                    &data,
+                );
+                let (_, data) = Frame::split(&frame).unwrap();
+                let p = crate::AdcDac::new(batches, data).unwrap();
+                assert_eq!(p.batch_size(), batch_size);
+                assert_eq!(
+                    crate::AdcDac::with_batch_size(batches, batch_size, data).unwrap(),
+                    p
+                );
+                for ch in 0..2 {
+                    assert!(p.adc(ch).enumerate().all(|(i, v)| v as usize == i * 4 + ch));
+                    assert!(p
+                        .dac(ch)
+                        .enumerate()
+                        .all(|(i, v)| v as usize == i * 4 + 2 + ch));
+                }
+                let traces = Frame::from_bytes(&frame).unwrap().payload.traces().unwrap();
+                assert!(traces.iter().all(|t| t.data.len() == batches * batch_size));
+            }
+        }
+        let mut b = AdcDacBuilder::default();
+        b.push([[1; 3]; 2], [[2; 3]; 2]);
+        let buf = b.frame(0);
+        let (_, data) = Frame::split(&buf).unwrap();
+        assert!(matches!(
+            crate::AdcDac::with_batch_size(1, 4, data),
+            Err(Error::TrailingBytes(_))
+        ));
+        assert!(matches!(
+            crate::AdcDac::with_batch_size(1, 0, data),
+            Err(Error::BatchSize(0))
+        ));
+        assert!(matches!(
+            crate::AdcDac::new(1, &data[..8 * 3 - 2]),
+            Err(Error::TrailingBytes(6))
+        ));
+    }
+}
@@ -1,143 +1,437 @@
 use super::Error;
+use crate::Trace;
 use core::fmt::Debug;
 
+/// Cast the payload data into exactly `batches` batches of `T`.
+fn cast_batches<T: bytemuck::Pod>(batches: usize, data: &[u8]) -> Result<&[T], Error> {
+    let trailing = data.len() % core::mem::size_of::<T>();
+    if trailing != 0 {
+        return Err(Error::TrailingBytes(trailing));
+    }
+    let data: &[T] = bytemuck::try_cast_slice(data)?;
+    if data.len() != batches {
+        return Err(Error::BatchCountMismatch {
+            expected: batches,
+            got: data.len(),
+        });
+    }
+    Ok(data)
+}
+
 pub trait Payload<'a>: Debug {
     fn new(batches: usize, data: &'a [u8]) -> Result<Self, Error>
     where
         Self: Sized;
-    fn traces(&self) -> Result<Vec<(&'static str, Vec<f32>)>, Error>;
+
+    /// Decode the payload into trace buffers.
+    ///
+    /// The buffers in `traces` are reused, see [`Trace::prepare()`].
+    ///
+    /// # Args
+    /// * `traces`: Trace buffers
+    /// * `append`: Append to the existing trace data instead of replacing it
+    fn traces_into(&self, traces: &mut Vec<Trace>, append: bool) -> Result<(), Error>;
+
+    fn traces(&self) -> Result<Vec<Trace>, Error> {
+        let mut traces = vec![];
+        self.traces_into(&mut traces, false)?;
+        Ok(traces)
+    }
+
+    /// The raw payload data in wire format.
+    fn as_bytes(&self) -> &[u8];
 }
 
+// The number of ADC samples per batch in the Stabilizer firmware.
+const BATCH_SIZE: usize = 8;
+
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub struct AdcDac<'a> {
-    data: &'a [[[[u8; 2]; 8]; 4]],
+    data: &'a [u8],
+    batch_size: usize,
+}
+
+impl<'a> AdcDac<'a> {
+    const CHANNELS: usize = 4;
+
+    /// The DAC output range in bipolar mode (including the external output op-amp) is +/- 4.096
+    /// V with 16-bit resolution. The anti-aliasing filter has an additional gain of 2.5.
+    pub const DAC_VOLT_PER_LSB: f32 = 4.096 * 2.5 / (1u16 << 15) as f32;
+    /// The ADC has a differential input with a range of +/- 4.096 V and 16-bit resolution.
+    /// The gain into the two inputs is 1/5.
+    pub const ADC_VOLT_PER_LSB: f32 = 5.0 / 2.0 * 4.096 / (1u16 << 15) as f32;
+
+    /// Extract AdcDacData with an explicit batch size.
+    ///
+    /// # Args
+    /// * `batches` - The number of batches in the frame.
+    /// * `batch_size` - The size of each batch in samples.
+    /// * `data` - The binary data composing the stream frame.
+    pub fn with_batch_size(
+        batches: usize,
+        batch_size: usize,
+        data: &'a [u8],
+    ) -> Result<Self, Error> {
+        if batch_size == 0 {
+            return Err(Error::BatchSize(batch_size));
+        }
+        let size = Self::CHANNELS * 2 * batch_size;
+        let trailing = data.len() % size;
+        if trailing != 0 {
+            return Err(Error::TrailingBytes(trailing));
+        }
+        if data.len() / size != batches {
+            return Err(Error::BatchCountMismatch {
+                expected: batches,
+                got: data.len() / size,
+            });
+        }
+        Ok(Self { data, batch_size })
+    }
+
+    /// The number of samples per channel in each batch
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    fn channel(&self, channel: usize) -> impl Iterator<Item = [u8; 2]> + '_ {
+        let size = 2 * self.batch_size;
+        self.data
+            .chunks_exact(Self::CHANNELS * size)
+            .flat_map(move |b| {
+                let b: &[[u8; 2]] = bytemuck::cast_slice(&b[channel * size..][..size]);
+                b.iter().copied()
+            })
+    }
+
+    /// Raw ADC codes of the given channel (0 or 1), two's complement
+    pub fn adc(&self, channel: usize) -> impl Iterator<Item = i16> + '_ {
+        assert!(channel < 2);
+        self.channel(channel).map(i16::from_le_bytes)
+    }
+
+    /// Raw DAC codes of the given channel (0 or 1), offset binary
+    pub fn dac(&self, channel: usize) -> impl Iterator<Item = u16> + '_ {
+        assert!(channel < 2);
+        self.channel(2 + channel).map(u16::from_le_bytes)
+    }
+
+    /// ADC input voltage of the given channel (0 or 1)
+    pub fn adc_volts(&self, channel: usize) -> impl Iterator<Item = f32> + '_ {
+        self.adc(channel).map(|v| v as f32 * Self::ADC_VOLT_PER_LSB)
+    }
+
+    /// DAC output voltage of the given channel (0 or 1)
+    pub fn dac_volts(&self, channel: usize) -> impl Iterator<Item = f32> + '_ {
+        self.dac(channel)
+            .map(|v| (v as i16).wrapping_add(i16::MIN) as f32 * Self::DAC_VOLT_PER_LSB)
+    }
 }
 
 impl<'a> Payload<'a> for AdcDac<'a> {
     /// Extract AdcDacData from a binary data block in the stream.
     ///
+    /// The batch size is inferred from the data length and the number of batches.
+    ///
     /// # Args
-    /// * `batch_size` - The size of each batch in samples.
+    /// * `batches` - The number of batches in the frame.
     /// * `data` - The binary data composing the stream frame.
     fn new(batches: usize, data: &'a [u8]) -> Result<Self, Error> {
-        const CHANNELS: usize = 4;
-        const BATCH_SIZE: usize = 8;
-        let data: &[[[[u8; 2]; BATCH_SIZE]; CHANNELS]] = bytemuck::try_cast_slice(data)?;
-        assert_eq!(data.len(), batches);
-        Ok(Self { data })
+        if batches == 0 {
+            return Self::with_batch_size(batches, BATCH_SIZE, data);
+        }
+        let trailing = data.len() % batches;
+        if trailing != 0 {
+            return Err(Error::TrailingBytes(trailing));
+        }
+        let size = data.len() / batches;
+        if size % (Self::CHANNELS * 2) != 0 {
+            return Err(Error::TrailingBytes(size % (Self::CHANNELS * 2)));
+        }
+        Self::with_batch_size(batches, size / (Self::CHANNELS * 2), data)
+    }
+
+    fn traces_into(&self, traces: &mut Vec<Trace>, append: bool) -> Result<(), Error> {
+        Trace::prepare(
+            traces,
+            [
+                Trace::new("ADC0", vec![]).with_unit("V", Self::ADC_VOLT_PER_LSB),
+                Trace::new("ADC1", vec![]).with_unit("V", Self::ADC_VOLT_PER_LSB),
+                Trace::new("DAC0", vec![]).with_unit("V", Self::DAC_VOLT_PER_LSB),
+                Trace::new("DAC1", vec![]).with_unit("V", Self::DAC_VOLT_PER_LSB),
+            ],
+            append,
+        );
+        for (i, trace) in traces[..2].iter_mut().enumerate() {
+            trace.data.extend(self.adc_volts(i));
+        }
+        for (i, trace) in traces[2..].iter_mut().enumerate() {
+            trace.data.extend(self.dac_volts(i));
+        }
+        Ok(())
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        self.data
+    }
+}
+
+/// Fls data of one channel in one batch
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlsChannel {
+    /// Demodulated signal, in-phase and quadrature
+    pub demod: [i32; 2],
+    /// Demodulated phase in counts, see [`FlsPhase`]
+    pub phase: i64,
+    /// DDS frequency tuning word
+    pub ftw: u32,
+    /// DDS phase offset word (low half word) and amplitude (high half word)
+    pub pow_amp: u32,
+    /// PLL phase detector output
+    pub pll: i32,
+}
+
+impl FlsChannel {
+    /// Parse from wire format words
+    pub fn from_words(w: &[[u8; 4]; 7]) -> Self {
+        let phase: &[[u8; 8]] = bytemuck::cast_slice(&w[2..4]);
+        Self {
+            demod: [i32::from_le_bytes(w[0]), i32::from_le_bytes(w[1])],
+            phase: i64::from_le_bytes(phase[0]),
+            ftw: u32::from_le_bytes(w[4]),
+            pow_amp: u32::from_le_bytes(w[5]),
+            pll: i32::from_le_bytes(w[6]),
+        }
+    }
+
+    /// Serialize into wire format words
+    pub fn to_words(&self) -> [[u8; 4]; 7] {
+        let phase = self.phase.to_le_bytes();
+        [
+            self.demod[0].to_le_bytes(),
+            self.demod[1].to_le_bytes(),
+            phase[..4].try_into().unwrap(),
+            phase[4..].try_into().unwrap(),
+            self.ftw.to_le_bytes(),
+            self.pow_amp.to_le_bytes(),
+            self.pll.to_le_bytes(),
+        ]
+    }
+
+    /// Magnitude of the demodulated signal in counts
+    pub fn magnitude(&self) -> f32 {
+        (self.demod[0] as f32).hypot(self.demod[1] as f32)
+    }
+
+    /// DDS phase offset word
+    pub fn pow(&self) -> u16 {
+        self.pow_amp as _
     }
 
-    fn traces(&self) -> Result<Vec<(&'static str, Vec<f32>)>, Error> {
-        // The DAC output range in bipolar mode (including the external output op-amp) is +/- 4.096
-        // V with 16-bit resolution. The anti-aliasing filter has an additional gain of 2.5.
-        const DAC_VOLT_PER_LSB: f32 = 4.096 * 2.5 / (1u16 << 15) as f32;
-        // The ADC has a differential input with a range of +/- 4.096 V and 16-bit resolution.
-        // The gain into the two inputs is 1/5.
-        const ADC_VOLT_PER_LSB: f32 = 5.0 / 2.0 * 4.096 / (1u16 << 15) as f32;
-        assert_eq!(DAC_VOLT_PER_LSB, ADC_VOLT_PER_LSB);
-
-        Ok(vec![
-            (
-                "ADC0",
-                self.data
-                    .iter()
-                    .flat_map(|b| {
-                        b[0].iter()
-                            .map(|v| i16::from_le_bytes(*v) as f32 * ADC_VOLT_PER_LSB)
-                    })
-                    .collect(),
-            ),
-            (
-                "ADC1",
-                self.data
-                    .iter()
-                    .flat_map(|b| {
-                        b[1].iter()
-                            .map(|v| i16::from_le_bytes(*v) as f32 * ADC_VOLT_PER_LSB)
-                    })
-                    .collect(),
-            ),
-            (
-                "DAC0",
-                self.data
-                    .iter()
-                    .flat_map(|b| {
-                        b[2].iter().map(|v| {
-                            i16::from_le_bytes(*v).wrapping_add(i16::MIN) as f32 * DAC_VOLT_PER_LSB
-                        })
-                    })
-                    .collect(),
-            ),
-            (
-                "DAC1",
-                self.data
-                    .iter()
-                    .flat_map(|b| {
-                        b[3].iter().map(|v| {
-                            i16::from_le_bytes(*v).wrapping_add(i16::MIN) as f32 * DAC_VOLT_PER_LSB
-                        })
-                    })
-                    .collect(),
-            ),
-        ])
+    /// DDS amplitude
+    pub fn amp(&self) -> u16 {
+        (self.pow_amp >> 16) as _
+    }
+}
+
+/// Fls phase decoder
+///
+/// Converts phase counts to radians.
+/// With unwrapping enabled the difference to the previous sample is accumulated
+/// across wraps of the 64 bit phase counter and the output is referenced to the first sample.
+/// The `f32` resolution of the phase trace is relative to the accumulated phase:
+/// it degrades as the phase grows over long runs. Use [`FlsPhase::reset()`] to re-reference.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct FlsPhase {
+    scale: f64,
+    unwrap: bool,
+    // Previous phase sample
+    last: Option<i64>,
+    // Phase accumulated since the first sample
+    phase: i64,
+}
+
+impl Default for FlsPhase {
+    fn default() -> Self {
+        Self::new((1u64 << 16) as _, false)
+    }
+}
+
+impl FlsPhase {
+    /// Create a new phase decoder
+    ///
+    /// # Args
+    /// * `scale`: Phase counts per turn (the firmware `phase_scale`)
+    /// * `unwrap`: Remove initial offset and unwrap
+    pub fn new(scale: f64, unwrap: bool) -> Self {
+        Self {
+            scale,
+            unwrap,
+            last: None,
+            phase: 0,
+        }
+    }
+
+    /// Radians per phase count
+    pub fn lsb(&self) -> f32 {
+        (core::f64::consts::TAU / self.scale) as _
+    }
+
+    /// Forget the phase offset and the accumulated phase
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.phase = 0;
+    }
+
+    /// Convert phase counts to radians
+    pub fn update(&mut self, phase: i64) -> f32 {
+        let phase = if self.unwrap {
+            if let Some(last) = self.last.replace(phase) {
+                self.phase = self.phase.wrapping_add(phase.wrapping_sub(last));
+            }
+            self.phase
+        } else {
+            phase
+        };
+        (phase as f64 * (core::f64::consts::TAU / self.scale)) as _
     }
 }
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub struct Fls<'a> {
     data: &'a [[[[u8; 4]; 7]; 2]],
+    batch_size: usize,
+    phase: FlsPhase,
+}
+
+impl<'a> Fls<'a> {
+    /// Trace names in output order.
+    ///
+    /// `R`: demodulated magnitude, `P`: demodulated phase,
+    /// `I`/`Q`: demodulated in-phase/quadrature,
+    /// `F`: DDS frequency tuning word, `POW`: DDS phase offset, `AMP`: DDS amplitude,
+    /// `PLL`: PLL phase detector
+    pub const NAMES: [&'static str; 16] = [
+        "AR", "AP", "BI", "BQ", "AI", "AQ", "AF", "APOW", "AAMP", "APLL", "BR", "BP", "BF", "BPOW",
+        "BAMP", "BPLL",
+    ];
+
+    // Trace index of [I, Q, R, P, F, POW, AMP, PLL] for each channel
+    const INDEX: [[usize; 8]; 2] = [[4, 5, 0, 1, 6, 7, 8, 9], [2, 3, 10, 11, 12, 13, 14, 15]];
+
+    /// Extract Fls data with an explicit batch size.
+    ///
+    /// The frame carries one sample per batch and not the batch size.
+    /// The batch size is the sample rate divider of the traces.
+    /// [`Payload::new()`] assumes the firmware default of 8 samples per batch.
+    ///
+    /// # Args
+    /// * `batches` - The number of batches in the frame.
+    /// * `batch_size` - The size of each batch in ADC samples.
+    /// * `data` - The binary data composing the stream frame.
+    pub fn with_batch_size(
+        batches: usize,
+        batch_size: usize,
+        data: &'a [u8],
+    ) -> Result<Self, Error> {
+        if batch_size == 0 {
+            return Err(Error::BatchSize(batch_size));
+        }
+        // demod_re, demod_im, phase[2], ftw, pow_amp, pll
+        let data: &[[[[u8; 4]; 7]; 2]] = cast_batches(batches, data)?;
+        Ok(Self {
+            data,
+            batch_size,
+            phase: Default::default(),
+        })
+    }
+
+    /// The number of ADC samples in each batch
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Set the phase decoder used by [`Payload::traces_into()`] for both channels
+    ///
+    /// Unwrapping is limited to the frame as no state is kept across frames.
+    /// Use [`Fls::traces_with()`] to unwrap across frames.
+    pub fn with_phase(mut self, phase: FlsPhase) -> Self {
+        self.phase = phase;
+        self
+    }
+
+    /// Data of the given channel (0 or 1) for each batch
+    pub fn channel(&self, channel: usize) -> impl Iterator<Item = FlsChannel> + '_ {
+        assert!(channel < 2);
+        self.data
+            .iter()
+            .map(move |b| FlsChannel::from_words(&b[channel]))
+    }
+
+    /// Decode into trace buffers using the given phase decoders for both channels
+    ///
+    /// See also [`Payload::traces_into()`].
+    pub fn traces_with(
+        &self,
+        phase: &mut [FlsPhase; 2],
+        traces: &mut Vec<Trace>,
+        append: bool,
+    ) -> Result<(), Error> {
+        const DEMOD_SCALE: f32 = 1.0 / i32::MAX as f32;
+        const FTW_SCALE: f32 = 1.0 / (1u64 << 32) as f32;
+        const POW_SCALE: f32 = core::f32::consts::TAU / (1u32 << 16) as f32;
+        const AMP_SCALE: f32 = 1.0 / (1u32 << 16) as f32;
+        let mut meta: [Trace; 16] = Default::default();
+        for (ch, (idx, phase)) in Self::INDEX.iter().zip(phase.iter()).enumerate() {
+            for (i, (unit, scale)) in idx.iter().zip([
+                ("", DEMOD_SCALE),
+                ("", DEMOD_SCALE),
+                ("", DEMOD_SCALE),
+                ("rad", phase.lsb()),
+                ("", FTW_SCALE),
+                ("rad", POW_SCALE),
+                ("", AMP_SCALE),
+                ("", 1.0),
+            ]) {
+                debug_assert!(Self::NAMES[*i].starts_with(["A", "B"][ch]));
+                meta[*i] = Trace::new(Self::NAMES[*i], vec![])
+                    .with_unit(unit, scale)
+                    .with_divider(self.batch_size as _);
+            }
+        }
+        Trace::prepare(traces, meta, append);
+        for (ch, (idx, phase)) in Self::INDEX.iter().zip(phase.iter_mut()).enumerate() {
+            for c in self.channel(ch) {
+                for (i, v) in idx.iter().zip([
+                    c.demod[0] as f32 * DEMOD_SCALE,
+                    c.demod[1] as f32 * DEMOD_SCALE,
+                    c.magnitude() * DEMOD_SCALE,
+                    phase.update(c.phase),
+                    c.ftw as f32 * FTW_SCALE,
+                    c.pow() as f32 * POW_SCALE,
+                    c.amp() as f32 * AMP_SCALE,
+                    c.pll as f32,
+                ]) {
+                    traces[*i].data.push(v);
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<'a> Payload<'a> for Fls<'a> {
     fn new(batches: usize, data: &'a [u8]) -> Result<Self, Error> {
-        let data: &[[[[u8; 4]; 7]; 2]] = bytemuck::try_cast_slice(data)?;
-        // demod_re, demod_im, phase[2], ftw, pow_amp, pll
-        assert_eq!(batches, data.len());
-        Ok(Self { data })
+        Self::with_batch_size(batches, BATCH_SIZE, data)
+    }
+
+    /// Decode into trace buffers using the phase decoder set with [`Fls::with_phase()`]
+    fn traces_into(&self, traces: &mut Vec<Trace>, append: bool) -> Result<(), Error> {
+        self.traces_with(&mut [self.phase; 2], traces, append)
     }
 
-    fn traces(&self) -> Result<Vec<(&'static str, Vec<f32>)>, Error> {
-        Ok(vec![
-            (
-                "AR",
-                self.data
-                    .iter()
-                    .map(|b| {
-                        ((i32::from_le_bytes(b[0][0]) as f32).powi(2)
-                            + (i32::from_le_bytes(b[0][1]) as f32).powi(2))
-                        .sqrt()
-                            * (1.0 / (i32::MAX as f32))
-                    })
-                    .collect(),
-            ),
-            (
-                "AP",
-                self.data
-                    .iter()
-                    .map(|b| {
-                        let b: &[[u8; 8]] = bytemuck::cast_slice(&b[0][2..4]);
-                        // FIXME: 1 << 16 is the default phase_scale[0]
-                        // TODO: deal with initial phase offset and dymanic range
-                        i64::from_le_bytes(b[0]) as f32
-                            * (core::f32::consts::TAU / (1i64 << 16) as f32)
-                    })
-                    .collect(),
-            ),
-            (
-                "BI",
-                self.data
-                    .iter()
-                    .map(|b| i32::from_le_bytes(b[1][0]) as f32 / i32::MAX as f32)
-                    .collect(),
-            ),
-            (
-                "BQ",
-                self.data
-                    .iter()
-                    .map(|b| i32::from_le_bytes(b[1][1]) as f32 / i32::MAX as f32)
-                    .collect(),
-            ),
-        ])
+    fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(self.data)
     }
 }
 
@@ -146,21 +440,155 @@ pub struct ThermostatEem<'a> {
     data: &'a [[[u8; 4]; 16 + 4]],
 }
 
+impl<'a> ThermostatEem<'a> {
+    /// Channel names and units in wire order.
+    ///
+    /// `Tac`: temperature of ADC `a` input channel `c`,
+    /// `Ic`: output current of channel `c`.
+    pub const CHANNELS: [(&'static str, &'static str); 16 + 4] = [
+        ("T00", "°C"),
+        ("T01", "°C"),
+        ("T02", "°C"),
+        ("T03", "°C"),
+        ("T10", "°C"),
+        ("T11", "°C"),
+        ("T12", "°C"),
+        ("T13", "°C"),
+        ("T20", "°C"),
+        ("T21", "°C"),
+        ("T22", "°C"),
+        ("T23", "°C"),
+        ("T30", "°C"),
+        ("T31", "°C"),
+        ("T32", "°C"),
+        ("T33", "°C"),
+        ("I0", "A"),
+        ("I1", "A"),
+        ("I2", "A"),
+        ("I3", "A"),
+    ];
+
+    /// Values of the given channel index, see [`Self::CHANNELS`]
+    pub fn channel(&self, channel: usize) -> impl Iterator<Item = f32> + '_ {
+        assert!(channel < Self::CHANNELS.len());
+        self.data
+            .iter()
+            .map(move |b| f32::from_le_bytes(b[channel]))
+    }
+}
+
 impl<'a> Payload<'a> for ThermostatEem<'a> {
     fn new(batches: usize, data: &'a [u8]) -> Result<Self, Error> {
-        let data: &[[[u8; 4]; 16 + 4]] = bytemuck::try_cast_slice(data)?;
-        assert_eq!(batches, data.len());
+        let data: &[[[u8; 4]; 16 + 4]] = cast_batches(batches, data)?;
         Ok(Self { data })
     }
 
-    fn traces(&self) -> Result<Vec<(&'static str, Vec<f32>)>, Error> {
-        Ok(["T00", "T20", "I0", "I1"]
-            .into_iter()
-            .zip(
-                [0, 8, 13, 16]
-                    .into_iter()
-                    .map(|i| self.data.iter().map(|b| f32::from_le_bytes(b[i])).collect()),
-            )
-            .collect())
+    fn traces_into(&self, traces: &mut Vec<Trace>, append: bool) -> Result<(), Error> {
+        Trace::prepare(
+            traces,
+            Self::CHANNELS.map(|(name, unit)| Trace::new(name, vec![]).with_unit(unit, 1.0)),
+            append,
+        );
+        for (i, trace) in traces.iter_mut().enumerate() {
+            trace.data.extend(self.channel(i));
+        }
+        Ok(())
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(self.data)
+    }
+}
+
+/// Demodulated lockin data
+///
+/// Each batch carries the in-phase and quadrature components as `i32`.
+/// This is not a stream format of the upstream firmware (the upstream `lockin` application
+/// streams [`AdcDac`]) and has no fixed format ID.
+/// The sources decode it with the format ID and batch size of the firmware that streams it
+/// (`--lockin` and `--lockin-batch-size`, see [`crate::source::SourceOpts`]).
+/// Alternatively register it:
+///
+/// ```
+/// use stabilizer_stream::{Format, Lockin, Payload, Registry};
+///
+/// Registry::global().write().unwrap().register(
+///     Format::Other(0x84),
+///     |batches, data| Ok(Box::new(Lockin::with_batch_size(batches, 16, data)?)),
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub struct Lockin<'a> {
+    data: &'a [[[u8; 4]; 2]],
+    batch_size: usize,
+}
+
+impl<'a> Lockin<'a> {
+    /// Extract Lockin data with an explicit batch size.
+    ///
+    /// The frame carries one sample per batch and not the batch size.
+    /// The batch size is the sample rate divider of the traces.
+    /// [`Payload::new()`] assumes 8 samples per batch.
+    ///
+    /// # Args
+    /// * `batches` - The number of batches in the frame.
+    /// * `batch_size` - The size of each batch in ADC samples.
+    /// * `data` - The binary data composing the stream frame.
+    pub fn with_batch_size(
+        batches: usize,
+        batch_size: usize,
+        data: &'a [u8],
+    ) -> Result<Self, Error> {
+        if batch_size == 0 {
+            return Err(Error::BatchSize(batch_size));
+        }
+        // in-phase, quadrature
+        let data: &[[[u8; 4]; 2]] = cast_batches(batches, data)?;
+        Ok(Self { data, batch_size })
+    }
+
+    /// The number of ADC samples in each batch
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Demodulated in-phase and quadrature components for each batch
+    pub fn iq(&self) -> impl Iterator<Item = [i32; 2]> + '_ {
+        self.data
+            .iter()
+            .map(|b| [i32::from_le_bytes(b[0]), i32::from_le_bytes(b[1])])
+    }
+}
+
+impl<'a> Payload<'a> for Lockin<'a> {
+    fn new(batches: usize, data: &'a [u8]) -> Result<Self, Error> {
+        Self::with_batch_size(batches, BATCH_SIZE, data)
+    }
+
+    fn traces_into(&self, traces: &mut Vec<Trace>, append: bool) -> Result<(), Error> {
+        const SCALE: f32 = 1.0 / i32::MAX as f32;
+        Trace::prepare(
+            traces,
+            [
+                Trace::new("I", vec![]).with_unit("", SCALE),
+                Trace::new("Q", vec![]).with_unit("", SCALE),
+                Trace::new("R", vec![]).with_unit("", SCALE),
+                Trace::new("P", vec![]).with_unit("rad", 1.0),
+            ]
+            .map(|t| t.with_divider(self.batch_size as _)),
+            append,
+        );
+        for [i, q] in self.iq() {
+            let (i, q) = (i as f32, q as f32);
+            traces[0].data.push(i * SCALE);
+            traces[1].data.push(q * SCALE);
+            traces[2].data.push(i.hypot(q) * SCALE);
+            traces[3].data.push(q.atan2(i));
+        }
+        Ok(())
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(self.data)
     }
 }
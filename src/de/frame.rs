@@ -1,13 +1,15 @@
-use super::data::{self, Payload};
-use super::{Error, Format};
+use super::data::Payload;
+use super::{Error, Format, Registry};
+use crate::Trace;
 
 // The magic word at the start of each stream frame.
-const MAGIC_WORD: [u8; 2] = [0x7b, 0x05];
+pub const MAGIC_WORD: [u8; 2] = [0x7b, 0x05];
 
 // The size of the frame header in bytes.
-const HEADER_SIZE: usize = 8;
+pub const HEADER_SIZE: usize = 8;
 
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Header {
     // The format code associated with the stream binary data.
     pub format: Format,
@@ -26,7 +28,7 @@ impl Header {
         if header[..2] != MAGIC_WORD {
             return Err(Error::InvalidHeader);
         }
-        let format = Format::try_from(header[2]).or(Err(Error::UnknownFormat))?;
+        let format = Format::from(header[2]);
         let batches = header[3];
         let seq = u32::from_le_bytes(header[4..8].try_into().unwrap());
         Ok(Self {
@@ -35,6 +37,16 @@ impl Header {
             seq,
         })
     }
+
+    /// Serialize the header of a stream frame.
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[..2].copy_from_slice(&MAGIC_WORD);
+        header[2] = self.format.into();
+        header[3] = self.batches;
+        header[4..8].copy_from_slice(&self.seq.to_le_bytes());
+        header
+    }
 }
 
 /// A single stream frame contains multiple batches of data.
@@ -46,15 +58,162 @@ pub struct Frame<'a> {
 
 impl<'a> Frame<'a> {
     /// Parse a stream frame from a single UDP packet.
+    ///
+    /// The payload is decoded using the constructors in the [`Registry::global()`].
     pub fn from_bytes(input: &'a [u8]) -> Result<Self, Error> {
-        let header = Header::parse(&input[..HEADER_SIZE].try_into().unwrap())?;
-        let data = &input[HEADER_SIZE..];
-        let batches = header.batches as _;
-        let payload: Box<dyn Payload> = match header.format {
-            Format::AdcDac => Box::new(data::AdcDac::new(batches, data)?),
-            Format::Fls => Box::new(data::Fls::new(batches, data)?),
-            Format::ThermostatEem => Box::new(data::ThermostatEem::new(batches, data)?),
-        };
+        Self::from_bytes_with(input, &Registry::global().read().unwrap())
+    }
+
+    /// Parse a stream frame from a single UDP packet using the given payload registry.
+    pub fn from_bytes_with(input: &'a [u8], registry: &Registry) -> Result<Self, Error> {
+        let (header, data) = Self::split(input)?;
+        let constructor = registry.get(header.format).ok_or(Error::UnknownFormat)?;
+        let payload = constructor(header.batches as _, data)?;
         Ok(Self { header, payload })
     }
+
+    /// Split a UDP packet into frame header and payload data.
+    pub fn split(input: &[u8]) -> Result<(Header, &[u8]), Error> {
+        if input.len() < HEADER_SIZE {
+            return Err(Error::Truncated {
+                expected: HEADER_SIZE,
+                got: input.len(),
+            });
+        }
+        let (header, data) = input.split_at(HEADER_SIZE);
+        Ok((Header::parse(header.try_into().unwrap())?, data))
+    }
+
+    /// Decode a stream frame from a single UDP packet directly into trace buffers.
+    ///
+    /// See [`Payload::traces_into()`] and [`Registry::decode_into()`].
+    ///
+    /// # Returns
+    /// The frame header
+    pub fn decode_into(
+        input: &[u8],
+        traces: &mut Vec<Trace>,
+        append: bool,
+    ) -> Result<Header, Error> {
+        Self::decode_into_with(input, &Registry::global().read().unwrap(), traces, append)
+    }
+
+    /// Decode a stream frame directly into trace buffers using the given payload registry.
+    pub fn decode_into_with(
+        input: &[u8],
+        registry: &Registry,
+        traces: &mut Vec<Trace>,
+        append: bool,
+    ) -> Result<Header, Error> {
+        let (header, data) = Self::split(input)?;
+        registry.decode_into(header.format, header.batches as _, data, traces, append)?;
+        Ok(header)
+    }
+
+    /// Serialize a stream frame into a single UDP packet.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        Self::encode(&self.header, self.payload.as_bytes())
+    }
+
+    /// Serialize a stream frame from a header and a raw payload.
+    pub fn encode(header: &Header, payload: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE + payload.len());
+        buf.extend_from_slice(&header.to_bytes());
+        buf.extend_from_slice(payload);
+        buf
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{PayloadBuilder, ThermostatEemBuilder};
+    use proptest::prelude::*;
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            Frame::from_bytes(&[0x7b]),
+            Err(Error::Truncated {
+                expected: HEADER_SIZE,
+                got: 1
+            })
+        ));
+        assert!(matches!(
+            Frame::from_bytes(&[0; HEADER_SIZE]),
+            Err(Error::InvalidHeader)
+        ));
+        let mut b = ThermostatEemBuilder::default();
+        b.push([0.0; 20]).push([0.0; 20]);
+        let mut buf = b.frame(0);
+        buf[3] = 3;
+        assert!(matches!(
+            Frame::from_bytes(&buf),
+            Err(Error::BatchCountMismatch {
+                expected: 3,
+                got: 2
+            })
+        ));
+        buf[3] = 2;
+        buf.push(0);
+        assert!(matches!(
+            Frame::from_bytes(&buf),
+            Err(Error::TrailingBytes(1))
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        for format in [Format::Fls, Format::Other(0x42)] {
+            let header = Header {
+                format,
+                batches: 3,
+                seq: 0xdead_beef,
+            };
+            let s = serde_json::to_string(&header).unwrap();
+            assert_eq!(serde_json::from_str::<Header>(&s).unwrap(), header);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_bytes(input in proptest::collection::vec(any::<u8>(), 0..2048)) {
+            let frame = Frame::from_bytes(&input);
+            if let Ok(frame) = frame {
+                frame.payload.traces().unwrap();
+            }
+        }
+
+        #[test]
+        fn arbitrary_payload(
+            format in 0u8..8,
+            batches: u8,
+            seq: u32,
+            payload in proptest::collection::vec(any::<u8>(), 0..2048),
+        ) {
+            let mut input = vec![MAGIC_WORD[0], MAGIC_WORD[1], format, batches];
+            input.extend_from_slice(&seq.to_le_bytes());
+            input.extend_from_slice(&payload);
+            let frame = Frame::from_bytes(&input);
+            if let Ok(frame) = frame {
+                frame.payload.traces().unwrap();
+                let (header, bytes) = (frame.header, frame.to_bytes());
+                prop_assert_eq!(header.batches, batches);
+                prop_assert_eq!(header.seq, seq);
+                prop_assert_eq!(&bytes[..], &input[..]);
+            }
+        }
+
+        #[test]
+        fn batch_aligned(format in 1u8..4, batches in 0u8..32, len in 0usize..64) {
+            // Payloads that are a whole multiple of a small unit, to hit the batch checks
+            let mut input = vec![MAGIC_WORD[0], MAGIC_WORD[1], format, batches, 0, 0, 0, 0];
+            input.resize(HEADER_SIZE + len * 16, 0x55);
+            let frame = Frame::from_bytes(&input);
+            if let Ok(frame) = frame {
+                frame.payload.traces().unwrap();
+            }
+        }
+    }
 }
@@ -1,18 +1,28 @@
-use num_enum::TryFromPrimitive;
+use num_enum::{FromPrimitive, IntoPrimitive};
 use thiserror::Error;
 
+mod builder;
+pub use builder::*;
 mod data;
 pub use data::*;
 mod frame;
 pub use frame::*;
+mod registry;
+pub use registry::*;
 
-#[derive(TryFromPrimitive, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(
+    FromPrimitive, IntoPrimitive, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 #[non_exhaustive]
 pub enum Format {
     AdcDac = 1,
     Fls = 2,
     ThermostatEem = 3,
+    /// Any other format ID, see [`Registry`]
+    #[num_enum(catch_all)]
+    Other(u8),
 }
 
 #[derive(Debug, Clone, Error)]
@@ -21,6 +31,14 @@ pub enum Error {
     InvalidHeader,
     #[error("Unknown format ID")]
     UnknownFormat,
+    #[error("Frame truncated: {got} of at least {expected} bytes")]
+    Truncated { expected: usize, got: usize },
+    #[error("Batch count mismatch: expected {expected}, got {got}")]
+    BatchCountMismatch { expected: usize, got: usize },
+    #[error("Payload has {0} trailing bytes")]
+    TrailingBytes(usize),
+    #[error("Invalid batch size: {0}")]
+    BatchSize(usize),
     #[error("Payload size")]
     PayloadSize(#[from] bytemuck::PodCastError),
 }
@@ -0,0 +1,226 @@
+use super::data::{self, Payload};
+use super::{Error, Format};
+use crate::Trace;
+use std::collections::{HashMap, HashSet};
+use std::sync::{OnceLock, RwLock};
+
+/// Payload constructor
+///
+/// Parses the payload data of a frame with the given number of batches.
+pub type Constructor = for<'a> fn(usize, &'a [u8]) -> Result<Box<dyn Payload<'a> + 'a>, Error>;
+
+/// Payload decoder
+///
+/// Decodes the payload data of a frame with the given number of batches
+/// directly into trace buffers, see [`Payload::traces_into()`].
+/// This avoids the allocation of a boxed [`Payload`].
+pub type Decoder = fn(usize, &[u8], &mut Vec<Trace>, bool) -> Result<(), Error>;
+
+/// Payload constructor registry
+///
+/// Maps stream format IDs to payload constructors.
+/// The default registry contains the formats supported by this crate.
+/// Custom formats (e.g. from custom Stabilizer firmware) can be added
+/// to the [`Registry::global()`] instance, which is used by [`super::Frame::from_bytes()`]
+/// and in turn by [`crate::source::Source`].
+///
+/// ```
+/// use stabilizer_stream::{Format, Registry, ThermostatEem, Payload};
+///
+/// Registry::global().write().unwrap().register(
+///     Format::Other(0x42),
+///     |batches, data| Ok(Box::new(ThermostatEem::new(batches, data)?)),
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct Registry {
+    constructors: HashMap<Format, (Constructor, Option<Decoder>)>,
+    // Formats with the payload of this crate
+    builtin: HashSet<Format>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut r = Self::empty();
+        r.register(Format::AdcDac, |batches, data| {
+            Ok(Box::new(data::AdcDac::new(batches, data)?))
+        });
+        r.register_decoder(Format::AdcDac, |batches, data, traces, append| {
+            data::AdcDac::new(batches, data)?.traces_into(traces, append)
+        });
+        r.register(Format::Fls, |batches, data| {
+            Ok(Box::new(data::Fls::new(batches, data)?))
+        });
+        r.register_decoder(Format::Fls, |batches, data, traces, append| {
+            data::Fls::new(batches, data)?.traces_into(traces, append)
+        });
+        r.register(Format::ThermostatEem, |batches, data| {
+            Ok(Box::new(data::ThermostatEem::new(batches, data)?))
+        });
+        r.register_decoder(Format::ThermostatEem, |batches, data, traces, append| {
+            data::ThermostatEem::new(batches, data)?.traces_into(traces, append)
+        });
+        r.builtin = r.formats().collect();
+        r
+    }
+}
+
+impl Registry {
+    /// A registry without any formats
+    pub fn empty() -> Self {
+        Self {
+            constructors: HashMap::new(),
+            builtin: HashSet::new(),
+        }
+    }
+
+    /// The process-wide registry
+    pub fn global() -> &'static RwLock<Self> {
+        static GLOBAL: OnceLock<RwLock<Registry>> = OnceLock::new();
+        GLOBAL.get_or_init(Default::default)
+    }
+
+    /// Register a payload constructor for a format ID.
+    ///
+    /// This removes any decoder registered for that format.
+    ///
+    /// # Returns
+    /// The constructor previously registered for that format, if any.
+    pub fn register(&mut self, format: Format, constructor: Constructor) -> Option<Constructor> {
+        self.builtin.remove(&format);
+        self.constructors
+            .insert(format, (constructor, None))
+            .map(|(c, _)| c)
+    }
+
+    /// Register an allocation-free decoder for a format ID.
+    ///
+    /// The decoder must be equivalent to the registered constructor
+    /// followed by [`Payload::traces_into()`].
+    ///
+    /// # Returns
+    /// The decoder previously registered for that format, if any.
+    pub fn register_decoder(&mut self, format: Format, decoder: Decoder) -> Option<Decoder> {
+        self.builtin.remove(&format);
+        self.constructors
+            .get_mut(&format)
+            .and_then(|(_, d)| d.replace(decoder))
+    }
+
+    /// Remove the constructor and decoder for a format ID.
+    pub fn unregister(&mut self, format: Format) -> Option<Constructor> {
+        self.builtin.remove(&format);
+        self.constructors.remove(&format).map(|(c, _)| c)
+    }
+
+    /// Look up the constructor for a format ID.
+    pub fn get(&self, format: Format) -> Option<Constructor> {
+        self.constructors.get(&format).map(|(c, _)| *c)
+    }
+
+    /// Whether a format is decoded by the payload of this crate
+    ///
+    /// This is no longer the case once the format is registered again.
+    /// Sources use this to apply their own decoding state (e.g. [`data::FlsPhase`])
+    /// only to the built-in payloads.
+    pub fn is_builtin(&self, format: Format) -> bool {
+        self.builtin.contains(&format)
+    }
+
+    /// Decode payload data of the given format into trace buffers.
+    ///
+    /// Uses the decoder if registered and falls back to the constructor.
+    pub fn decode_into(
+        &self,
+        format: Format,
+        batches: usize,
+        data: &[u8],
+        traces: &mut Vec<Trace>,
+        append: bool,
+    ) -> Result<(), Error> {
+        match self.constructors.get(&format) {
+            Some((_, Some(decoder))) => decoder(batches, data, traces, append),
+            Some((constructor, None)) => constructor(batches, data)?.traces_into(traces, append),
+            None => Err(Error::UnknownFormat),
+        }
+    }
+
+    /// Registered format IDs
+    pub fn formats(&self) -> impl Iterator<Item = Format> + '_ {
+        self.constructors.keys().copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Frame;
+
+    #[derive(Debug)]
+    struct Custom<'a>(&'a [u8]);
+
+    impl<'a> Payload<'a> for Custom<'a> {
+        fn new(batches: usize, data: &'a [u8]) -> Result<Self, Error> {
+            if data.len() != batches {
+                return Err(Error::BatchCountMismatch {
+                    expected: batches,
+                    got: data.len(),
+                });
+            }
+            Ok(Self(data))
+        }
+
+        fn traces_into(&self, traces: &mut Vec<Trace>, append: bool) -> Result<(), Error> {
+            Trace::prepare(traces, [Trace::new("X", vec![])], append);
+            traces[0].data.extend(self.0.iter().map(|x| *x as f32));
+            Ok(())
+        }
+
+        fn as_bytes(&self) -> &[u8] {
+            self.0
+        }
+    }
+
+    #[test]
+    fn custom() {
+        let header = crate::Header {
+            format: Format::Other(0x42),
+            batches: 3,
+            seq: 9,
+        };
+        let buf = Frame::encode(&header, &[1, 2, 3]);
+        let mut registry = Registry::default();
+        assert!(registry.is_builtin(Format::Fls));
+        assert!(!registry.is_builtin(Format::Other(0x42)));
+        assert!(matches!(
+            Frame::from_bytes_with(&buf, &registry),
+            Err(Error::UnknownFormat)
+        ));
+        assert!(registry
+            .register(Format::from(0x42), |batches, data| {
+                Ok(Box::new(Custom::new(batches, data)?))
+            })
+            .is_none());
+        let frame = Frame::from_bytes_with(&buf, &registry).unwrap();
+        assert_eq!(frame.header, header);
+        assert_eq!(
+            frame.payload.traces().unwrap(),
+            [Trace::new("X", vec![1.0, 2.0, 3.0])]
+        );
+        let mut traces = vec![];
+        registry
+            .decode_into(Format::Other(0x42), 3, &buf[8..], &mut traces, false)
+            .unwrap();
+        registry
+            .decode_into(Format::Other(0x42), 3, &buf[8..], &mut traces, true)
+            .unwrap();
+        assert_eq!(traces[0].data, [1.0, 2.0, 3.0, 1.0, 2.0, 3.0]);
+        assert!(!registry.is_builtin(Format::Other(0x42)));
+        assert!(registry.unregister(Format::Other(0x42)).is_some());
+        registry.register(Format::Fls, |batches, data| {
+            Ok(Box::new(Custom::new(batches, data)?))
+        });
+        assert!(!registry.is_builtin(Format::Fls));
+        assert!(Frame::from_bytes_with(&buf, &registry).is_err());
+    }
+}
@@ -0,0 +1,58 @@
+/// Policy for the samples of lost batches
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Gap {
+    /// Leave the samples out, the time base is not continuous
+    #[default]
+    Drop,
+    /// Insert zeros
+    Zero,
+    /// Repeat the last value
+    Hold,
+    /// Interpolate linearly between the last and the next value
+    Linear,
+}
+
+impl Gap {
+    /// Insert `n` samples at index `at`
+    ///
+    /// `before` is the value preceding the gap, if known.
+    /// Without it, the value following the gap is used.
+    pub fn fill(&self, data: &mut Vec<f32>, at: usize, n: usize, before: Option<f32>) {
+        let after = data.get(at).copied();
+        let before = before.or(after).unwrap_or_default();
+        let after = after.unwrap_or(before);
+        match self {
+            Self::Drop => {}
+            Self::Zero => {
+                data.splice(at..at, core::iter::repeat(0.0).take(n));
+            }
+            Self::Hold => {
+                data.splice(at..at, core::iter::repeat(before).take(n));
+            }
+            Self::Linear => {
+                let step = (after - before) / (n + 1) as f32;
+                data.splice(at..at, (1..=n).map(|i| before + step * i as f32));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fill() {
+        for (gap, filled) in [
+            (Gap::Drop, &[0.0, 4.0][..]),
+            (Gap::Zero, &[0.0, 0.0, 0.0, 0.0, 4.0]),
+            (Gap::Hold, &[0.0, 1.0, 1.0, 1.0, 4.0]),
+            (Gap::Linear, &[0.0, 1.75, 2.5, 3.25, 4.0]),
+        ] {
+            let mut data = vec![0.0, 4.0];
+            gap.fill(&mut data, 1, 3, Some(1.0));
+            assert_eq!(data, filled);
+        }
+    }
+}
@@ -6,11 +6,25 @@ mod psd;
 pub use psd::*;
 mod loss;
 pub use loss::*;
+mod reorder;
+pub use reorder::*;
+mod gap;
+pub use gap::*;
 mod var;
 pub use var::*;
+mod trace;
+pub use trace::*;
+mod capture;
+pub use capture::*;
+mod acquire;
+pub use acquire::*;
 
 pub mod source;
 
+pub mod record;
+
+pub mod pcap;
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Frame deserialization error")]
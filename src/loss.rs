@@ -1,6 +1,6 @@
-use crate::Frame;
+use crate::Header;
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Loss {
     received: u64,
     dropped: u64,
@@ -8,21 +8,26 @@ pub struct Loss {
 }
 
 impl Loss {
-    pub fn update(&mut self, frame: &Frame) {
-        self.received += frame.header.batches as u64;
+    pub fn update(&mut self, header: &Header) {
+        self.received += header.batches as u64;
         if let Some(seq) = self.seq {
-            let missing = frame.header.seq.wrapping_sub(seq) as u64;
+            let missing = header.seq.wrapping_sub(seq) as u64;
             self.dropped += missing;
             if missing > 0 {
                 log::warn!(
                     "Lost {} batches: {:#08X} -> {:#08X}",
                     missing,
                     seq,
-                    frame.header.seq,
+                    header.seq,
                 );
             }
         }
-        self.seq = Some(frame.header.seq.wrapping_add(frame.header.batches as _));
+        self.seq = Some(header.seq.wrapping_add(header.batches as _));
+    }
+
+    /// Number of batches missing before the frame
+    pub fn gap(&self, header: &Header) -> u32 {
+        self.seq.map_or(0, |seq| header.seq.wrapping_sub(seq))
     }
 
     pub fn analyze(&self) {
@@ -0,0 +1,686 @@
+//! pcap and pcapng capture files of UDP streams
+
+use std::{
+    io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write},
+    net::{IpAddr, SocketAddr},
+    ops::Range,
+    time::{Duration, SystemTime},
+};
+
+const PCAP_MICROS: u32 = 0xa1b2c3d4;
+const PCAP_NANOS: u32 = 0xa1b23c4d;
+const PCAPNG_SECTION: u32 = 0x0a0d0d0a;
+const PCAPNG_BYTE_ORDER: u32 = 0x1a2b3c4d;
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const PROTO_UDP: u8 = 17;
+
+// Upper limit on record sizes to reject corrupt files
+const MAX_PACKET: usize = 1 << 24;
+
+/// Whether the data is the start of a pcap or pcapng file
+pub fn is_pcap(head: &[u8]) -> bool {
+    let Some(magic) = head.get(..4) else {
+        return false;
+    };
+    let magic: [u8; 4] = magic.try_into().unwrap();
+    [PCAP_MICROS, PCAP_NANOS, PCAPNG_SECTION]
+        .iter()
+        .any(|m| magic == m.to_le_bytes() || magic == m.to_be_bytes())
+}
+
+/// A UDP datagram from a capture file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Datagram<'a> {
+    /// Capture timestamp
+    pub timestamp: SystemTime,
+    /// Source address
+    pub src: SocketAddr,
+    /// Destination address
+    pub dst: SocketAddr,
+    /// UDP payload
+    pub payload: &'a [u8],
+}
+
+/// Datagram filter
+///
+/// Unset fields match any datagram.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Destination port
+    pub port: Option<u16>,
+    /// Source or destination address
+    pub addr: Option<IpAddr>,
+}
+
+impl Filter {
+    /// Whether the datagram passes the filter
+    pub fn matches(&self, datagram: &Datagram) -> bool {
+        self.port.map_or(true, |p| datagram.dst.port() == p)
+            && self
+                .addr
+                .map_or(true, |a| datagram.src.ip() == a || datagram.dst.ip() == a)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Pcap {
+        big: bool,
+        nanos: bool,
+        linktype: u32,
+    },
+    PcapNg {
+        big: bool,
+        // Link type and timestamp ticks per second of each interface
+        interfaces: Vec<(u32, u64)>,
+    },
+}
+
+fn u16_at(buf: &[u8], i: usize, big: bool) -> u16 {
+    let b = buf[i..i + 2].try_into().unwrap();
+    if big {
+        u16::from_be_bytes(b)
+    } else {
+        u16::from_le_bytes(b)
+    }
+}
+
+fn u32_at(buf: &[u8], i: usize, big: bool) -> u32 {
+    let b = buf[i..i + 4].try_into().unwrap();
+    if big {
+        u32::from_be_bytes(b)
+    } else {
+        u32::from_le_bytes(b)
+    }
+}
+
+fn invalid(msg: &'static str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Read the rest of a pcapng section header block
+///
+/// `head` contains the block length and the byte order magic.
+/// Returns whether the section is big endian.
+fn read_section(reader: &mut impl Read, head: [u8; 8]) -> Result<bool> {
+    let big = match u32::from_le_bytes(head[4..].try_into().unwrap()) {
+        PCAPNG_BYTE_ORDER => false,
+        m if m.swap_bytes() == PCAPNG_BYTE_ORDER => true,
+        _ => return Err(invalid("Invalid pcapng byte order magic")),
+    };
+    let len = u32_at(&head, 0, big) as usize;
+    if len < 28 || len % 4 != 0 {
+        return Err(invalid("Invalid pcapng section header length"));
+    }
+    std::io::copy(
+        &mut reader.by_ref().take((len - 12) as u64),
+        &mut std::io::sink(),
+    )?;
+    Ok(big)
+}
+
+/// Read into the buffer, `false` on a clean end of file
+fn read_exact_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        // A truncated trailing record (e.g. from an interrupted capture) ends the file
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// pcap and pcapng file reader
+///
+/// Reads the UDP datagrams in IPv4 or IPv6 packets over Ethernet (including VLAN tags),
+/// Linux cooked capture, BSD loopback or raw IP links.
+/// Other packets are skipped.
+#[derive(Debug)]
+pub struct PcapReader<R> {
+    reader: R,
+    kind: Kind,
+    buf: Vec<u8>,
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Read the file header
+    pub fn new(mut reader: R) -> Result<Self> {
+        let kind = Self::read_header(&mut reader)?;
+        Ok(Self {
+            reader,
+            kind,
+            buf: vec![],
+        })
+    }
+
+    fn read_header(reader: &mut R) -> Result<Kind> {
+        let mut head = [0; 4];
+        reader.read_exact(&mut head)?;
+        let magic = u32::from_le_bytes(head);
+        if magic == PCAPNG_SECTION {
+            let mut head = [0; 8];
+            reader.read_exact(&mut head)?;
+            return Ok(Kind::PcapNg {
+                big: read_section(reader, head)?,
+                interfaces: vec![],
+            });
+        }
+        let (big, nanos) = match magic {
+            PCAP_MICROS => (false, false),
+            PCAP_NANOS => (false, true),
+            m if m.swap_bytes() == PCAP_MICROS => (true, false),
+            m if m.swap_bytes() == PCAP_NANOS => (true, true),
+            _ => return Err(invalid("Not a pcap file")),
+        };
+        let mut header = [0; 20];
+        reader.read_exact(&mut header)?;
+        Ok(Kind::Pcap {
+            big,
+            nanos,
+            linktype: u32_at(&header, 16, big) & 0xffff,
+        })
+    }
+
+    /// Read the next packet into the buffer
+    ///
+    /// Returns the link type and the timestamp.
+    fn next_packet(&mut self) -> Result<Option<(u32, SystemTime)>> {
+        match &mut self.kind {
+            Kind::Pcap {
+                big,
+                nanos,
+                linktype,
+            } => {
+                let mut head = [0; 16];
+                if !read_exact_or_eof(&mut self.reader, &mut head)? {
+                    return Ok(None);
+                }
+                let secs = u32_at(&head, 0, *big) as u64;
+                let frac = u32_at(&head, 4, *big);
+                let len = u32_at(&head, 8, *big) as usize;
+                if len > MAX_PACKET {
+                    return Err(invalid("Invalid pcap packet length"));
+                }
+                self.buf.resize(len, 0);
+                if !read_exact_or_eof(&mut self.reader, &mut self.buf)? {
+                    return Ok(None);
+                }
+                let frac = if *nanos {
+                    frac
+                } else {
+                    frac.saturating_mul(1000)
+                };
+                let timestamp = SystemTime::UNIX_EPOCH
+                    .checked_add(Duration::new(secs, frac))
+                    .ok_or(invalid("Invalid pcap timestamp"))?;
+                Ok(Some((*linktype, timestamp)))
+            }
+            Kind::PcapNg { big, interfaces } => loop {
+                let mut head = [0; 8];
+                if !read_exact_or_eof(&mut self.reader, &mut head)? {
+                    return Ok(None);
+                }
+                // The section header block type is byte order independent
+                if u32::from_le_bytes(head[..4].try_into().unwrap()) == PCAPNG_SECTION {
+                    head.copy_within(4.., 0);
+                    self.reader.read_exact(&mut head[4..])?;
+                    *big = read_section(&mut self.reader, head)?;
+                    interfaces.clear();
+                    continue;
+                }
+                let typ = u32_at(&head, 0, *big);
+                let len = u32_at(&head, 4, *big) as usize;
+                if len < 12 || len % 4 != 0 || len > MAX_PACKET {
+                    return Err(invalid("Invalid pcapng block length"));
+                }
+                self.buf.resize(len - 8, 0);
+                if !read_exact_or_eof(&mut self.reader, &mut self.buf)? {
+                    return Ok(None);
+                }
+                let body = &self.buf[..len - 12];
+                match typ {
+                    // Interface description
+                    1 => {
+                        if body.len() < 8 {
+                            return Err(invalid("Invalid pcapng interface"));
+                        }
+                        let linktype = u16_at(body, 0, *big) as u32;
+                        let mut resolution = 1_000_000;
+                        let mut options = &body[8..];
+                        while options.len() >= 4 {
+                            let code = u16_at(options, 0, *big);
+                            let n = u16_at(options, 2, *big) as usize;
+                            if code == 0 {
+                                break;
+                            }
+                            let value = options
+                                .get(4..4 + n)
+                                .ok_or(invalid("Invalid pcapng interface option"))?;
+                            if code == 9 && n == 1 {
+                                let r = value[0];
+                                resolution = if r & 0x80 == 0 {
+                                    10u64.checked_pow(r as _)
+                                } else {
+                                    1u64.checked_shl((r & 0x7f) as _)
+                                }
+                                .ok_or(invalid("Invalid pcapng timestamp resolution"))?;
+                            }
+                            options = options.get(4 + ((n + 3) & !3)..).unwrap_or_default();
+                        }
+                        interfaces.push((linktype, resolution));
+                    }
+                    // Enhanced packet
+                    6 => {
+                        if body.len() < 20 {
+                            return Err(invalid("Invalid pcapng packet"));
+                        }
+                        let interface = u32_at(body, 0, *big) as usize;
+                        let &(linktype, resolution) = interfaces
+                            .get(interface)
+                            .ok_or(invalid("Unknown pcapng interface"))?;
+                        let ticks =
+                            (u32_at(body, 4, *big) as u64) << 32 | u32_at(body, 8, *big) as u64;
+                        let len = (u32_at(body, 12, *big) as usize).min(body.len() - 20);
+                        let timestamp = SystemTime::UNIX_EPOCH
+                            .checked_add(Duration::new(
+                                ticks / resolution,
+                                ((ticks % resolution) as u128 * 1_000_000_000 / resolution as u128)
+                                    as u32,
+                            ))
+                            .ok_or(invalid("Invalid pcapng timestamp"))?;
+                        self.buf.copy_within(20..20 + len, 0);
+                        self.buf.truncate(len);
+                        return Ok(Some((linktype, timestamp)));
+                    }
+                    // Simple packet, without timestamp
+                    3 => {
+                        if body.len() < 4 {
+                            return Err(invalid("Invalid pcapng packet"));
+                        }
+                        let &(linktype, _) = interfaces
+                            .first()
+                            .ok_or(invalid("Unknown pcapng interface"))?;
+                        let len = (u32_at(body, 0, *big) as usize).min(body.len() - 4);
+                        self.buf.copy_within(4..4 + len, 0);
+                        self.buf.truncate(len);
+                        return Ok(Some((linktype, SystemTime::UNIX_EPOCH)));
+                    }
+                    _ => {}
+                }
+            },
+        }
+    }
+
+    /// Read the next UDP datagram
+    ///
+    /// Returns `None` at the end of the file.
+    pub fn next_udp(&mut self) -> Result<Option<Datagram<'_>>> {
+        loop {
+            let Some((linktype, timestamp)) = self.next_packet()? else {
+                return Ok(None);
+            };
+            if let Some((src, dst, payload)) = decapsulate(linktype, &self.buf) {
+                return Ok(Some(Datagram {
+                    timestamp,
+                    src,
+                    dst,
+                    payload: &self.buf[payload],
+                }));
+            }
+        }
+    }
+
+    /// The underlying reader
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Unwrap the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read + Seek> PcapReader<R> {
+    /// Restart at the beginning of the file
+    pub fn rewind(&mut self) -> Result<()> {
+        self.reader.seek(SeekFrom::Start(0))?;
+        self.kind = Self::read_header(&mut self.reader)?;
+        Ok(())
+    }
+}
+
+/// Strip link, IP and UDP headers
+///
+/// Returns source and destination addresses and the range of the UDP payload.
+fn decapsulate(linktype: u32, packet: &[u8]) -> Option<(SocketAddr, SocketAddr, Range<usize>)> {
+    let start = match linktype {
+        LINKTYPE_NULL => 4,
+        LINKTYPE_ETHERNET => {
+            let mut i = 12;
+            let mut ethertype = u16_at(packet.get(..i + 2)?, i, true);
+            // 802.1Q and 802.1ad tags
+            while ethertype == 0x8100 || ethertype == 0x88a8 {
+                i += 4;
+                ethertype = u16_at(packet.get(..i + 2)?, i, true);
+            }
+            if ethertype != 0x0800 && ethertype != 0x86dd {
+                return None;
+            }
+            i + 2
+        }
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => 0,
+        LINKTYPE_LINUX_SLL => 16,
+        LINKTYPE_LINUX_SLL2 => 20,
+        _ => return None,
+    };
+    let ip = packet.get(start..)?;
+    let (src, dst, udp) = match ip.first()? >> 4 {
+        4 => {
+            let ihl = (ip[0] & 0xf) as usize * 4;
+            let len = (u16_at(ip.get(..4)?, 2, true) as usize).min(ip.len());
+            let fragment = u16_at(ip.get(..8)?, 6, true);
+            // Skip non-UDP and fragmented packets
+            if ihl < 20 || ip.get(9)? != &PROTO_UDP || fragment & 0x3fff != 0 {
+                return None;
+            }
+            let src: [u8; 4] = ip.get(12..16)?.try_into().unwrap();
+            let dst: [u8; 4] = ip.get(16..20)?.try_into().unwrap();
+            (IpAddr::from(src), IpAddr::from(dst), ihl..len.max(ihl))
+        }
+        6 => {
+            // Extension headers are not supported
+            if ip.get(6)? != &PROTO_UDP {
+                return None;
+            }
+            let len = u16_at(ip.get(..6)?, 4, true) as usize;
+            let src: [u8; 16] = ip.get(8..24)?.try_into().unwrap();
+            let dst: [u8; 16] = ip.get(24..40)?.try_into().unwrap();
+            (
+                IpAddr::from(src),
+                IpAddr::from(dst),
+                40..(40 + len).min(ip.len()),
+            )
+        }
+        _ => return None,
+    };
+    let udp_start = start + udp.start;
+    let udp = ip.get(udp)?;
+    let src_port = u16_at(udp.get(..8)?, 0, true);
+    let dst_port = u16_at(udp, 2, true);
+    let len = (u16_at(udp, 4, true) as usize).clamp(8, udp.len());
+    Some((
+        SocketAddr::new(src, src_port),
+        SocketAddr::new(dst, dst_port),
+        udp_start + 8..udp_start + len,
+    ))
+}
+
+/// One's complement sum of big endian 16 bit words
+fn checksum(sum: u32, data: &[u8]) -> u32 {
+    data.chunks(2).fold(sum, |sum, c| {
+        sum + u16::from_be_bytes([c[0], c.get(1).copied().unwrap_or(0)]) as u32
+    })
+}
+
+fn fold(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// pcap file writer
+///
+/// Writes UDP datagrams as raw IP packets with nanosecond timestamps.
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    writer: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Write the file header
+    pub fn new(mut writer: W) -> Result<Self> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_NANOS.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&0i32.to_le_bytes()); // timezone
+        header.extend_from_slice(&0u32.to_le_bytes()); // accuracy
+        header.extend_from_slice(&0xffffu32.to_le_bytes()); // snaplen
+        header.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        writer.write_all(&header)?;
+        Ok(Self {
+            writer,
+            buf: vec![],
+        })
+    }
+
+    /// Write a UDP datagram
+    ///
+    /// Source and destination must be of the same address family.
+    pub fn write_udp(
+        &mut self,
+        timestamp: SystemTime,
+        src: SocketAddr,
+        dst: SocketAddr,
+        payload: &[u8],
+    ) -> Result<()> {
+        let udp_len = 8 + payload.len();
+        let buf = &mut self.buf;
+        buf.clear();
+        // pseudo header checksum
+        let sum = match (src.ip(), dst.ip()) {
+            (IpAddr::V4(s), IpAddr::V4(d)) => {
+                let total = u16::try_from(20 + udp_len)
+                    .map_err(|_| Error::new(ErrorKind::InvalidInput, "Datagram too long"))?;
+                buf.extend_from_slice(&[0x45, 0]);
+                buf.extend_from_slice(&total.to_be_bytes());
+                buf.extend_from_slice(&[0, 0, 0x40, 0, 64, PROTO_UDP, 0, 0]);
+                buf.extend_from_slice(&s.octets());
+                buf.extend_from_slice(&d.octets());
+                let sum = fold(checksum(0, buf));
+                buf[10..12].copy_from_slice(&sum.to_be_bytes());
+                checksum(
+                    checksum(checksum(0, &s.octets()), &d.octets()),
+                    &[0, PROTO_UDP],
+                ) + udp_len as u32
+            }
+            (IpAddr::V6(s), IpAddr::V6(d)) => {
+                let len = u16::try_from(udp_len)
+                    .map_err(|_| Error::new(ErrorKind::InvalidInput, "Datagram too long"))?;
+                buf.extend_from_slice(&[0x60, 0, 0, 0]);
+                buf.extend_from_slice(&len.to_be_bytes());
+                buf.extend_from_slice(&[PROTO_UDP, 64]);
+                buf.extend_from_slice(&s.octets());
+                buf.extend_from_slice(&d.octets());
+                checksum(
+                    checksum(checksum(0, &s.octets()), &d.octets()),
+                    &[0, PROTO_UDP],
+                ) + udp_len as u32
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Mixed address families",
+                ))
+            }
+        };
+        let udp = buf.len();
+        buf.extend_from_slice(&src.port().to_be_bytes());
+        buf.extend_from_slice(&dst.port().to_be_bytes());
+        buf.extend_from_slice(&(udp_len as u16).to_be_bytes());
+        buf.extend_from_slice(&[0, 0]);
+        buf.extend_from_slice(payload);
+        let sum = match fold(checksum(sum, &buf[udp..])) {
+            0 => 0xffff,
+            sum => sum,
+        };
+        buf[udp + 6..udp + 8].copy_from_slice(&sum.to_be_bytes());
+
+        let t = timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut head = [0; 16];
+        head[..4].copy_from_slice(&(t.as_secs() as u32).to_le_bytes());
+        head[4..8].copy_from_slice(&t.subsec_nanos().to_le_bytes());
+        head[8..12].copy_from_slice(&(buf.len() as u32).to_le_bytes());
+        head[12..].copy_from_slice(&(buf.len() as u32).to_le_bytes());
+        self.writer.write_all(&head)?;
+        self.writer.write_all(buf)
+    }
+
+    /// Flush the underlying writer
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+
+    /// Unwrap the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let t = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        let src: SocketAddr = "10.0.0.2:1234".parse().unwrap();
+        let dst: SocketAddr = "10.0.0.1:9293".parse().unwrap();
+        let mut w = PcapWriter::new(vec![]).unwrap();
+        w.write_udp(t, src, dst, &[1, 2, 3]).unwrap();
+        w.write_udp(
+            t,
+            "[::1]:5".parse().unwrap(),
+            "[::2]:6".parse().unwrap(),
+            &[4],
+        )
+        .unwrap();
+        let buf = w.into_inner();
+        assert!(is_pcap(&buf));
+        // IPv4 header checksum
+        assert_eq!(fold(checksum(0, &buf[40..60])), 0);
+
+        let mut r = PcapReader::new(&buf[..]).unwrap();
+        let d = r.next_udp().unwrap().unwrap();
+        assert_eq!(
+            d,
+            Datagram {
+                timestamp: t,
+                src,
+                dst,
+                payload: &[1, 2, 3]
+            }
+        );
+        assert!(Filter {
+            port: Some(9293),
+            addr: Some(src.ip())
+        }
+        .matches(&d));
+        assert!(!Filter {
+            port: Some(1234),
+            addr: None
+        }
+        .matches(&d));
+        let d = r.next_udp().unwrap().unwrap();
+        assert_eq!(d.dst, "[::2]:6".parse().unwrap());
+        assert_eq!(d.payload, [4]);
+        assert!(r.next_udp().unwrap().is_none());
+    }
+
+    #[test]
+    fn pcapng() {
+        // Ethernet frame with VLAN tag around the IPv4/UDP packet
+        let mut w = PcapWriter::new(vec![]).unwrap();
+        w.write_udp(
+            SystemTime::UNIX_EPOCH,
+            "10.0.0.2:1234".parse().unwrap(),
+            "10.0.0.1:9293".parse().unwrap(),
+            &[7; 5],
+        )
+        .unwrap();
+        let buf = w.into_inner();
+        let ip = &buf[40..];
+        let mut eth = vec![0; 12];
+        eth.extend_from_slice(&[0x81, 0x00, 0, 1, 0x08, 0x00]);
+        eth.extend_from_slice(ip);
+
+        let block = |typ: u32, body: &[u8]| {
+            let len = (12 + ((body.len() + 3) & !3)) as u32;
+            let mut b = vec![];
+            b.extend_from_slice(&typ.to_be_bytes());
+            b.extend_from_slice(&len.to_be_bytes());
+            b.extend_from_slice(body);
+            b.resize(len as usize - 4, 0);
+            b.extend_from_slice(&len.to_be_bytes());
+            b
+        };
+        let mut section = PCAPNG_BYTE_ORDER.to_be_bytes().to_vec();
+        section.extend_from_slice(&[0, 1, 0, 0]);
+        section.extend_from_slice(&(-1i64).to_be_bytes());
+        let mut interface = vec![0, 1, 0, 0, 0, 0, 0, 0];
+        // if_tsresol: nanoseconds
+        interface.extend_from_slice(&[0, 9, 0, 1, 9, 0, 0, 0, 0, 0, 0, 0]);
+        let mut packet = vec![];
+        packet.extend_from_slice(&0u32.to_be_bytes());
+        let ticks = 1_500_000_000u64;
+        packet.extend_from_slice(&((ticks >> 32) as u32).to_be_bytes());
+        packet.extend_from_slice(&(ticks as u32).to_be_bytes());
+        packet.extend_from_slice(&(eth.len() as u32).to_be_bytes());
+        packet.extend_from_slice(&(eth.len() as u32).to_be_bytes());
+        packet.extend_from_slice(&eth);
+        let buf = [
+            block(PCAPNG_SECTION, &section),
+            block(1, &interface),
+            block(5, &[0; 8]),
+            block(6, &packet),
+        ]
+        .concat();
+        assert!(is_pcap(&buf));
+
+        let mut r = PcapReader::new(&buf[..]).unwrap();
+        let d = r.next_udp().unwrap().unwrap();
+        assert_eq!(
+            d.timestamp,
+            SystemTime::UNIX_EPOCH + Duration::new(1, 500_000_000)
+        );
+        assert_eq!(d.dst, "10.0.0.1:9293".parse().unwrap());
+        assert_eq!(d.payload, [7; 5]);
+        assert!(r.next_udp().unwrap().is_none());
+
+        // if_tsresol: seconds, with a timestamp beyond the range of `SystemTime`
+        let n = interface.len();
+        interface[n - 8] = 0;
+        packet[4..12].copy_from_slice(&[0xff; 8]);
+        let buf = [
+            block(PCAPNG_SECTION, &section),
+            block(1, &interface),
+            block(6, &packet),
+        ]
+        .concat();
+        let mut r = PcapReader::new(&buf[..]).unwrap();
+        let e = r.next_udp().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        // if_tsresol without its value
+        let buf = [
+            block(PCAPNG_SECTION, &section),
+            block(1, &[0, 1, 0, 0, 0, 0, 0, 0, 0, 9, 0, 1]),
+        ]
+        .concat();
+        let mut r = PcapReader::new(&buf[..]).unwrap();
+        let e = r.next_udp().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -56,6 +56,7 @@ impl<const N: usize> Window<N> {
 
 /// Detrend method
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Detrend {
     /// No detrending
     #[default]
@@ -292,6 +293,7 @@ impl<const N: usize> PsdStage for Psd<N> {
 
 /// Stage break information
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Break {
     /// Start index in PSD and frequencies
     pub start: usize,
@@ -345,6 +347,7 @@ impl Break {
 
 /// PSD segment merge options
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MergeOpts {
     /// Remove low resolution bins
     pub remove_overlap: bool,
@@ -366,6 +369,7 @@ impl Default for MergeOpts {
 
 /// Averaging options
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AvgOpts {
     /// Averaring limit
     pub limit: u32,
@@ -657,4 +661,33 @@ mod test {
                 .all(|p| (p * 0.5 - 1.0).abs() < 10.0 / (b.count as f32).sqrt()));
         }
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let opts = (
+            Detrend::Span,
+            MergeOpts {
+                remove_overlap: false,
+                min_count: 3,
+                remove_transition_band: false,
+            },
+            AvgOpts { limit: 5, count: 7 },
+        );
+        let s = serde_json::to_string(&opts).unwrap();
+        assert_eq!(
+            serde_json::from_str::<(Detrend, MergeOpts, AvgOpts)>(&s).unwrap(),
+            opts
+        );
+
+        const N: usize = 1 << 6;
+        let mut d = PsdCascade::<N>::new(1);
+        d.process(&[1.0; 1 << 10]);
+        let psd = d.psd(&MergeOpts::default());
+        let s = serde_json::to_string(&psd).unwrap();
+        assert_eq!(
+            serde_json::from_str::<(Vec<f32>, Vec<Break>)>(&s).unwrap(),
+            psd
+        );
+    }
 }
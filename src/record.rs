@@ -0,0 +1,250 @@
+use crate::{CaptureHeader, Frame};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// File rotation policy
+///
+/// A new file is started before a frame that would exceed either limit.
+/// Frames are never split across files.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rotation {
+    /// Maximum file size in bytes
+    pub size: Option<u64>,
+    /// Maximum time span per file
+    pub time: Option<Duration>,
+}
+
+impl Rotation {
+    /// Whether any rotation limit is set
+    pub fn is_enabled(&self) -> bool {
+        self.size.is_some() || self.time.is_some()
+    }
+}
+
+/// Writes complete stream frames to files
+///
+/// The files can be replayed with [`crate::source::FileSource`].
+/// A change of the frame size starts a new file so that the frame size is constant per file.
+/// If a capture header template is set, each file starts with a [`CaptureHeader`].
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    rotation: Rotation,
+    file: Option<BufWriter<File>>,
+    index: usize,
+    size: u64,
+    opened: Instant,
+    frame_size: Option<usize>,
+    header: Option<CaptureHeader>,
+}
+
+impl Recorder {
+    /// Record to `path`
+    ///
+    /// With rotation enabled, an index is appended to the file stem:
+    /// `capture.bin` becomes `capture-0000.bin`, `capture-0001.bin`, ...
+    /// Without rotation, only files after the first one (started by a frame size change)
+    /// get an index.
+    pub fn new(path: impl Into<PathBuf>, rotation: Rotation) -> Self {
+        Self {
+            path: path.into(),
+            rotation,
+            file: None,
+            index: 0,
+            size: 0,
+            opened: Instant::now(),
+            frame_size: None,
+            header: None,
+        }
+    }
+
+    /// Set the capture header template
+    ///
+    /// Format and frame size are taken from the first frame of each file.
+    /// The start time defaults to the time the file is created.
+    /// `None` writes headerless frame dumps.
+    pub fn set_header(&mut self, header: Option<CaptureHeader>) {
+        self.header = header;
+    }
+
+    /// The capture header template
+    pub fn header_mut(&mut self) -> Option<&mut CaptureHeader> {
+        self.header.as_mut()
+    }
+
+    /// Path of the file with the given index
+    pub fn file_path(&self, index: usize) -> PathBuf {
+        if !self.rotation.is_enabled() && index == 0 {
+            return self.path.clone();
+        }
+        let mut name = self.path.file_stem().unwrap_or_default().to_os_string();
+        name.push(format!("-{index:04}"));
+        if let Some(ext) = self.path.extension() {
+            name.push(".");
+            name.push(ext);
+        }
+        self.path.with_file_name(name)
+    }
+
+    /// Number of files started
+    pub fn files(&self) -> usize {
+        self.index + self.file.is_some() as usize
+    }
+
+    /// Append a frame, rotating the file if needed
+    pub fn write(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        match self.frame_size {
+            Some(size) if size != frame.len() => {
+                log::warn!("Frame size changed: {} -> {}", size, frame.len());
+                self.frame_size = Some(frame.len());
+                self.close()?;
+            }
+            Some(_) => {}
+            None => {
+                log::info!("Frame size: {}", frame.len());
+                self.frame_size = Some(frame.len());
+            }
+        }
+        if self.file.is_some() && self.size > 0 {
+            let full = self
+                .rotation
+                .size
+                .is_some_and(|size| self.size + frame.len() as u64 > size);
+            let expired = self
+                .rotation
+                .time
+                .is_some_and(|time| self.opened.elapsed() >= time);
+            if full || expired {
+                self.close()?;
+            }
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let path = self.file_path(self.index);
+                log::info!("Writing {}", path.display());
+                self.size = 0;
+                self.opened = Instant::now();
+                let file = self.file.insert(BufWriter::new(File::create(path)?));
+                if let Some(template) = &self.header {
+                    let header = CaptureHeader {
+                        format: Frame::split(frame).ok().map(|(h, _)| h.format),
+                        frame_size: frame.len(),
+                        start: template.start.or_else(|| Some(SystemTime::now())),
+                        ..template.clone()
+                    };
+                    let header = header.to_bytes();
+                    file.write_all(&header)?;
+                    self.size += header.len() as u64;
+                }
+                file
+            }
+        };
+        file.write_all(frame)?;
+        self.size += frame.len() as u64;
+        Ok(())
+    }
+
+    /// Flush and close the current file
+    ///
+    /// The next frame starts a new file.
+    pub fn close(&mut self) -> std::io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+            self.index += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::PayloadBuilder;
+
+    #[test]
+    fn rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rec = Recorder::new(
+            dir.path().join("capture.bin"),
+            Rotation {
+                size: Some(10),
+                time: None,
+            },
+        );
+        for i in 0..5u8 {
+            rec.write(&[i; 4]).unwrap();
+        }
+        rec.close().unwrap();
+        assert_eq!(rec.files(), 3);
+        assert_eq!(
+            std::fs::read(dir.path().join("capture-0000.bin")).unwrap(),
+            [[0; 4], [1; 4]].concat()
+        );
+        assert_eq!(
+            std::fs::read(dir.path().join("capture-0002.bin")).unwrap(),
+            [4; 4]
+        );
+    }
+
+    #[test]
+    fn frame_size() {
+        use crate::source::{FileSource, TraceSource};
+        let dir = tempfile::tempdir().unwrap();
+        let mut rec = Recorder::new(dir.path().join("capture.bin"), Rotation::default());
+        rec.set_header(Some(CaptureHeader::default()));
+        let mut thermostat = crate::ThermostatEemBuilder::default();
+        thermostat.push([1.0; 20]);
+        let mut adcdac = crate::AdcDacBuilder::default();
+        adcdac.push([[1; 8]; 2], [[0; 8]; 2]);
+        for seq in 0..2 {
+            rec.write(&thermostat.frame(seq)).unwrap();
+        }
+        for seq in 2..4 {
+            rec.write(&adcdac.frame(seq)).unwrap();
+        }
+        rec.close().unwrap();
+        assert_eq!(rec.files(), 2);
+
+        let mut traces = vec![];
+        let mut source = FileSource::new(dir.path().join("capture.bin"), None, false).unwrap();
+        for _ in 0..2 {
+            source.get(&mut traces).unwrap();
+            assert_eq!(traces[0].data, [1.0]);
+        }
+        assert!(source.get(&mut traces).is_err());
+        let mut source = FileSource::new(dir.path().join("capture-0001.bin"), None, false).unwrap();
+        for _ in 0..2 {
+            source.get(&mut traces).unwrap();
+            assert_eq!(traces[0].name, "ADC0");
+            assert_eq!(traces[0].data.len(), 8);
+        }
+        assert!(source.get(&mut traces).is_err());
+    }
+
+    #[test]
+    fn header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("record.bin");
+        let mut rec = Recorder::new(&path, Rotation::default());
+        rec.set_header(Some(CaptureHeader {
+            notes: "test".into(),
+            ..Default::default()
+        }));
+        let frame = crate::ThermostatEemBuilder::default().frame(7);
+        rec.write(&frame).unwrap();
+        rec.close().unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let mut reader = &data[..];
+        let header = CaptureHeader::detect(&mut reader).unwrap().unwrap();
+        assert_eq!(header.format, Some(crate::Format::ThermostatEem));
+        assert_eq!(header.frame_size, frame.len());
+        assert_eq!(header.notes, "test");
+        assert!(header.start.is_some());
+        assert_eq!(reader, frame);
+    }
+}
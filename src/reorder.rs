@@ -0,0 +1,160 @@
+use crate::{Error, Frame};
+use std::collections::VecDeque;
+
+/// Sequence number based reorder buffer
+///
+/// Frames are buffered in a bounded window and released in `Header::seq` order.
+/// When the window is full, the oldest frame is released and the gap before it
+/// is given up (and counted as loss downstream).
+///
+/// Reordered, duplicate and late (arriving after their gap was given up) frames
+/// are counted in batches.
+/// A frame more than two windows behind the expected sequence number is taken as a
+/// sequence reset.
+#[derive(Clone, Debug, Default)]
+pub struct Reorder {
+    window: usize,
+    next: Option<u32>,
+    // (seq, batches, frame), in sequence order
+    pending: VecDeque<(u32, u8, Vec<u8>)>,
+    released: VecDeque<u32>,
+    spare: Vec<Vec<u8>>,
+    reordered: u64,
+    duplicate: u64,
+    late: u64,
+}
+
+impl Reorder {
+    /// Reorder within a window of frames
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            ..Default::default()
+        }
+    }
+
+    /// Window size in frames
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Add a frame and pass the frames that are ready to `release`, in order
+    pub fn push<E: From<Error>>(
+        &mut self,
+        frame: &[u8],
+        mut release: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let (header, _) = Frame::split(frame).map_err(Error::from)?;
+        let (seq, batches) = (header.seq, header.batches);
+        let next = *self.next.get_or_insert(seq);
+        let ahead = seq.wrapping_sub(next) as i32;
+        if ahead < 0 {
+            if ahead.unsigned_abs() as u64 <= 2 * self.window as u64 * batches.max(1) as u64 {
+                if self.released.contains(&seq) {
+                    self.duplicate += batches as u64;
+                } else {
+                    self.late += batches as u64;
+                }
+                return Ok(());
+            }
+            log::warn!("Sequence reset: {:#08X} -> {:#08X}", next, seq);
+            self.flush(&mut release)?;
+            self.next = Some(seq);
+        }
+        let next = self.next.unwrap();
+        let pos = self
+            .pending
+            .partition_point(|(s, ..)| s.wrapping_sub(next) < seq.wrapping_sub(next));
+        if self.pending.get(pos).is_some_and(|(s, ..)| *s == seq) {
+            self.duplicate += batches as u64;
+            return Ok(());
+        }
+        if pos < self.pending.len() {
+            self.reordered += batches as u64;
+        }
+        let mut buf = self.spare.pop().unwrap_or_default();
+        buf.clear();
+        buf.extend_from_slice(frame);
+        self.pending.insert(pos, (seq, batches, buf));
+        while self
+            .pending
+            .front()
+            .is_some_and(|(s, ..)| Some(*s) == self.next || self.pending.len() > self.window)
+        {
+            self.release(&mut release)?;
+        }
+        Ok(())
+    }
+
+    /// Release all buffered frames, in order
+    pub fn flush<E>(&mut self, mut release: impl FnMut(&[u8]) -> Result<(), E>) -> Result<(), E> {
+        while !self.pending.is_empty() {
+            self.release(&mut release)?;
+        }
+        Ok(())
+    }
+
+    fn release<E>(&mut self, release: impl FnOnce(&[u8]) -> Result<(), E>) -> Result<(), E> {
+        let (seq, batches, buf) = self.pending.pop_front().unwrap();
+        self.next = Some(seq.wrapping_add(batches as _));
+        if self.released.len() > self.window {
+            self.released.pop_front();
+        }
+        self.released.push_back(seq);
+        let ret = release(&buf);
+        self.spare.push(buf);
+        ret
+    }
+
+    /// Batches that arrived out of order and were put back in order
+    pub fn reordered(&self) -> u64 {
+        self.reordered
+    }
+
+    /// Batches that were received more than once
+    pub fn duplicate(&self) -> u64 {
+        self.duplicate
+    }
+
+    /// Batches that arrived after their gap was given up
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    pub fn analyze(&self) {
+        log::info!(
+            "Reordered: {}, duplicate: {}, late: {} batches",
+            self.reordered,
+            self.duplicate,
+            self.late
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{PayloadBuilder, ThermostatEemBuilder};
+
+    #[test]
+    fn reorder() {
+        let mut b = ThermostatEemBuilder::default();
+        b.push([0.0; 20]);
+        let mut r = Reorder::new(2);
+        let mut out = vec![];
+        for seq in [0, 2, 1, 1, 3, 6, 5, 7, 4, 0x1000] {
+            r.push(&b.frame(seq), |f| {
+                out.push(Frame::split(f)?.0.seq);
+                Ok::<_, Error>(())
+            })
+            .unwrap();
+        }
+        r.flush(|f| {
+            out.push(Frame::split(f)?.0.seq);
+            Ok::<_, Error>(())
+        })
+        .unwrap();
+        assert_eq!(out, [0, 1, 2, 3, 5, 6, 7, 0x1000]);
+        assert_eq!((r.reordered(), r.duplicate(), r.late()), (2, 1, 1));
+    }
+}
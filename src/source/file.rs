@@ -0,0 +1,379 @@
+use super::{Decoder, TraceSource};
+use crate::{
+    pcap::{is_pcap, Filter, PcapReader},
+    CaptureHeader, FlsPhase, Format, Frame, Gap, Trace, HEADER_SIZE,
+};
+use anyhow::Result;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom},
+    path::Path,
+    time::SystemTime,
+};
+
+/// Detect the frame size of a headerless frame dump
+///
+/// The frame size is the smallest size for which the following frames in `buf` start
+/// with frame headers of the same format and number of batches as the first one.
+/// If `buf` is the entire dump, a single frame is also accepted.
+fn detect_frame_size(buf: &[u8], complete: bool) -> Result<usize> {
+    // Number of frames to check
+    const FRAMES: usize = 16;
+    let (first, _) = Frame::split(buf)?;
+    let matches = |at: usize| {
+        Frame::split(&buf[at..at + HEADER_SIZE])
+            .is_ok_and(|(h, _)| h.format == first.format && h.batches == first.batches)
+    };
+    let size = (HEADER_SIZE..=buf.len() - HEADER_SIZE)
+        .find(|&size| {
+            (1..FRAMES)
+                .map(|i| i * size)
+                .take_while(|at| at + HEADER_SIZE <= buf.len())
+                .all(matches)
+        })
+        .or(complete.then_some(buf.len()));
+    size.ok_or_else(|| anyhow::anyhow!("Frame size not found, set it explicitly"))
+}
+
+#[derive(Debug)]
+enum Input {
+    Frames {
+        reader: BufReader<File>,
+        buf: Vec<u8>,
+        start: u64,
+    },
+    Pcap(PcapReader<BufReader<File>>),
+}
+
+/// Stream frames from a file
+///
+/// Supported are capture files with a [`CaptureHeader`], headerless dumps of fixed size frames
+/// and pcap/pcapng captures of the UDP stream.
+#[derive(Debug)]
+pub struct FileSource {
+    input: Input,
+    repeat: bool,
+    decoder: Decoder,
+    header: Option<CaptureHeader>,
+    filter: Filter,
+    timestamp: Option<SystemTime>,
+}
+
+impl FileSource {
+    /// Open a file of frames.
+    ///
+    /// The frame size is taken from the capture header if present.
+    /// In headerless frame dumps, it is `frame_size` if given and detected from the
+    /// frame headers otherwise.
+    /// With `repeat`, wrap around at the end of the file.
+    pub fn new(path: impl AsRef<Path>, frame_size: Option<usize>, repeat: bool) -> Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut reader = BufReader::with_capacity(1 << 20, file);
+        let mut header = None;
+        let input = if is_pcap(reader.fill_buf()?) {
+            Input::Pcap(PcapReader::new(reader)?)
+        } else {
+            header = CaptureHeader::detect(&mut reader)?;
+            let frame_size = if let Some(header) = &header {
+                log::info!("Capture: {:?}", header);
+                header.frame_size
+            } else if let Some(frame_size) = frame_size {
+                frame_size
+            } else {
+                let pos = reader.stream_position()?;
+                let buf = reader.fill_buf()?;
+                let frame_size = detect_frame_size(buf, pos + buf.len() as u64 == len)?;
+                log::info!("Frame size: {}", frame_size);
+                frame_size
+            };
+            anyhow::ensure!(frame_size > 0, "Invalid frame size");
+            Input::Frames {
+                start: reader.stream_position()?,
+                reader,
+                buf: vec![0; frame_size],
+            }
+        };
+        Ok(Self {
+            input,
+            repeat,
+            decoder: Decoder::default(),
+            header,
+            filter: Filter::default(),
+            timestamp: None,
+        })
+    }
+
+    /// The capture header, if the file has one
+    pub fn header(&self) -> Option<&CaptureHeader> {
+        self.header.as_ref()
+    }
+
+    /// Set the FLS phase scaling and unwrapping
+    pub fn set_fls_phase(&mut self, phase: FlsPhase) {
+        self.decoder.set_fls_phase(phase);
+    }
+
+    /// Put frames back in sequence order within a window of frames
+    ///
+    /// A window of zero disables reordering.
+    pub fn set_reorder(&mut self, window: usize) {
+        self.decoder.set_reorder(window);
+    }
+
+    /// Set the policy for the samples of lost batches
+    pub fn set_gap(&mut self, gap: Gap) {
+        self.decoder.set_gap(gap);
+    }
+
+    /// Decode frames with the given format ID as [`crate::Lockin`] data
+    ///
+    /// `batch_size` is the number of ADC samples per batch, see [`crate::Lockin::with_batch_size()`].
+    pub fn set_lockin(&mut self, format: Format, batch_size: usize) {
+        self.decoder.set_lockin(format, batch_size);
+    }
+
+    /// Set the datagram filter for pcap captures
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+}
+
+impl TraceSource for FileSource {
+    fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()> {
+        match &mut self.input {
+            Input::Frames { reader, buf, start } => loop {
+                match reader.read_exact(buf) {
+                    Ok(()) => {
+                        if self.decoder.decode(buf, traces)? {
+                            return Ok(());
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof && self.repeat => {
+                        reader.seek(SeekFrom::Start(*start))?;
+                    }
+                    Err(e) => {
+                        if e.kind() == ErrorKind::UnexpectedEof && self.decoder.flush(traces)? {
+                            return Ok(());
+                        }
+                        Err(e)?
+                    }
+                }
+            },
+            Input::Pcap(reader) => loop {
+                match reader.next_udp()? {
+                    Some(datagram) if self.filter.matches(&datagram) => {
+                        self.timestamp = Some(datagram.timestamp);
+                        if self.decoder.decode(datagram.payload, traces)? {
+                            return Ok(());
+                        }
+                    }
+                    Some(_) => {}
+                    None if self.repeat => reader.rewind()?,
+                    None if self.decoder.flush(traces)? => return Ok(()),
+                    None => Err(std::io::Error::from(ErrorKind::UnexpectedEof))?,
+                }
+            },
+        }
+    }
+
+    /// Capture timestamp of the most recent pcap packet
+    fn timestamp(&self) -> Option<SystemTime> {
+        self.timestamp
+    }
+
+    /// Sample rate from the capture header
+    fn sample_rate(&self) -> Option<f64> {
+        self.header.as_ref()?.sample_rate
+    }
+
+    fn finish(&self) {
+        self.decoder.analyze()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AdcDacBuilder, PayloadBuilder, ThermostatEemBuilder};
+    use std::io::Write;
+
+    #[test]
+    fn frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frames.bin");
+        let mut b = ThermostatEemBuilder::default();
+        b.push([0.0; 20]).push([1.0; 20]);
+        let frame = b.frame(0);
+        let mut f = File::create(&path).unwrap();
+        f.write_all(&frame).unwrap();
+        f.write_all(&b.frame(2)).unwrap();
+        drop(f);
+
+        let mut source = FileSource::new(&path, None, true).unwrap();
+        let mut traces = vec![];
+        for _ in 0..3 {
+            source.get(&mut traces).unwrap();
+            assert_eq!(traces[0].name, "T00");
+            assert_eq!(traces[0].data, [0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn frame_size() {
+        let mut b = AdcDacBuilder::default();
+        b.push([[0x7b05; 4]; 2], [[0; 4]; 2])
+            .push([[1; 4]; 2], [[2; 4]; 2]);
+        let frame = b.frame(0);
+        let data: Vec<u8> = [0, 2, 8, 10].iter().flat_map(|seq| b.frame(*seq)).collect();
+        assert_eq!(detect_frame_size(&data, true).unwrap(), frame.len());
+        assert_eq!(detect_frame_size(&data[..100], false).unwrap(), frame.len());
+        assert_eq!(detect_frame_size(&frame, true).unwrap(), frame.len());
+        assert!(detect_frame_size(&frame, false).is_err());
+        assert!(detect_frame_size(&frame[1..], true).is_err());
+    }
+
+    #[test]
+    fn lockin() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lockin.bin");
+        let mut b = crate::LockinBuilder::new(Format::Other(0x84));
+        b.push([i32::MAX, 0]);
+        std::fs::write(&path, b.frame(0)).unwrap();
+
+        let mut traces = vec![];
+        let mut source = FileSource::new(&path, None, false).unwrap();
+        assert!(source.get(&mut traces).is_err());
+        let mut source = FileSource::new(&path, None, false).unwrap();
+        source.set_lockin(Format::Other(0x84), 16);
+        source.get(&mut traces).unwrap();
+        assert_eq!(traces[0].name, "I");
+        assert_eq!(traces[0].data, [1.0]);
+        assert_eq!(traces[0].divider, 16);
+    }
+
+    #[test]
+    fn reorder() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reorder.bin");
+        let mut data = vec![];
+        for seq in [0, 2, 1, 3] {
+            let mut b = ThermostatEemBuilder::default();
+            b.push([seq as f32 / 4.0; 20]);
+            data.extend(b.frame(seq));
+        }
+        std::fs::write(&path, &data).unwrap();
+
+        let mut source = FileSource::new(&path, None, false).unwrap();
+        source.set_reorder(2);
+        let mut traces = vec![];
+        let mut samples = vec![];
+        while source.get(&mut traces).is_ok() {
+            samples.extend(traces[0].data.iter().map(|x| (x * 4.0).round() as i32));
+        }
+        assert_eq!(samples, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn gap() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gap.bin");
+        let mut data = vec![];
+        for seq in [0, 3] {
+            let mut b = ThermostatEemBuilder::default();
+            b.push([seq as f32 / 4.0; 20]);
+            data.extend(b.frame(seq));
+        }
+        std::fs::write(&path, &data).unwrap();
+
+        let mut source = FileSource::new(&path, Some(data.len() / 2), false).unwrap();
+        source.set_gap(Gap::Linear);
+        let mut traces = vec![];
+        let mut samples = vec![];
+        while source.get(&mut traces).is_ok() {
+            samples.extend(traces[0].data.iter().map(|x| (x * 4.0).round() as i32));
+        }
+        assert_eq!(samples, [0, 1, 2, 3]);
+        assert_eq!(source.decoder.filled, (2, 40));
+    }
+
+    #[test]
+    fn gap_format_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gap-format.pcap");
+        let mut a = AdcDacBuilder::default();
+        a.push([[1; 8]; 2], [[0; 8]; 2]);
+        let mut l = ThermostatEemBuilder::default();
+        l.push([1.0; 20]);
+        let t = SystemTime::UNIX_EPOCH;
+        let src = "10.0.0.2:1234".parse().unwrap();
+        let dst = "10.0.0.1:9293".parse().unwrap();
+        let mut w = crate::pcap::PcapWriter::new(vec![]).unwrap();
+        for frame in [a.frame(0), a.frame(2), l.frame(4)] {
+            w.write_udp(t, src, dst, &frame).unwrap();
+        }
+        std::fs::write(&path, w.into_inner()).unwrap();
+
+        let mut source = FileSource::new(&path, None, false).unwrap();
+        source.set_reorder(4);
+        source.set_gap(Gap::Hold);
+        let mut traces = vec![];
+        source.get(&mut traces).unwrap();
+        assert_eq!(traces[0].name, "ADC0");
+        // The AdcDac frame after the gap is replaced by the ThermostatEem frame in the same block
+        source.get(&mut traces).unwrap();
+        assert_eq!(traces[0].name, "T00");
+        assert_eq!(traces[0].data, [1.0]);
+        assert!(source.get(&mut traces).is_err());
+    }
+
+    #[test]
+    fn capture() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.bin");
+        let mut b = ThermostatEemBuilder::default();
+        b.push([1.0; 20]);
+        let frame = b.frame(0);
+        let header = CaptureHeader {
+            frame_size: frame.len(),
+            notes: "test".into(),
+            ..Default::default()
+        };
+        std::fs::write(&path, [header.to_bytes(), frame].concat()).unwrap();
+
+        let mut source = FileSource::new(&path, None, true).unwrap();
+        assert_eq!(source.header(), Some(&header));
+        let mut traces = vec![];
+        for _ in 0..2 {
+            source.get(&mut traces).unwrap();
+            assert_eq!(traces[0].data, [1.0]);
+        }
+    }
+
+    #[test]
+    fn pcap() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.pcap");
+        let mut b = ThermostatEemBuilder::default();
+        b.push([1.0; 20]);
+        let t = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1);
+        let src = "10.0.0.2:1234".parse().unwrap();
+        let mut w = crate::pcap::PcapWriter::new(vec![]).unwrap();
+        w.write_udp(t, src, "10.0.0.1:9000".parse().unwrap(), &[0; 8])
+            .unwrap();
+        w.write_udp(t, src, "10.0.0.1:9293".parse().unwrap(), &b.frame(0))
+            .unwrap();
+        std::fs::write(&path, w.into_inner()).unwrap();
+
+        let mut source = FileSource::new(&path, None, false).unwrap();
+        source.set_filter(Filter {
+            port: Some(9293),
+            addr: None,
+        });
+        let mut traces = vec![];
+        source.get(&mut traces).unwrap();
+        assert_eq!(traces[0].data, [1.0]);
+        assert_eq!(source.timestamp(), Some(t));
+        assert!(source.get(&mut traces).is_err());
+    }
+}
@@ -0,0 +1,626 @@
+use crate::{
+    pcap::Filter, Fls, FlsPhase, Format, Frame, Gap, Header, Lockin, Loss, Payload, Registry,
+    Reorder, Trace,
+};
+use anyhow::Result;
+use clap::Parser;
+use std::{net::SocketAddrV4, time::SystemTime};
+
+mod file;
+pub use file::*;
+mod multi;
+pub use multi::*;
+mod raw;
+pub use raw::*;
+mod synth;
+pub use synth::*;
+mod text;
+pub use text::*;
+mod udp;
+pub use udp::*;
+mod wav;
+pub use wav::*;
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(feature = "async")]
+pub use stream::*;
+
+/// Stabilizer stream source options
+#[derive(Parser, Debug, Clone)]
+pub struct SourceOpts {
+    /// The local IP to receive streaming data on.
+    ///
+    /// For pcap files: only use packets from or to this address unless unspecified.
+    #[arg(short, long, default_value = "0.0.0.0")]
+    ip: std::net::Ipv4Addr,
+
+    /// The UDP port to receive streaming data on.
+    ///
+    /// For pcap files: only use packets to this port.
+    #[arg(short, long, default_value_t = 9293)]
+    port: u16,
+
+    /// Use frames from the given file (capture, frame dump, pcap or pcapng), a WAV file
+    /// or delimited text (`.csv`, `.tsv`, `.txt`, see the `--csv-*` options)
+    ///
+    /// Repeat to combine several files.
+    #[arg(short, long)]
+    file: Vec<String>,
+
+    /// Receive from these UDP endpoints instead (ip:port, comma separated)
+    #[arg(long, value_delimiter = ',')]
+    endpoint: Vec<std::net::SocketAddrV4>,
+
+    /// Text file field delimiter (default: detect tab, comma, semicolon or whitespace)
+    #[arg(long)]
+    csv_delimiter: Option<char>,
+
+    /// Text file time column name or index (default: first column if named `time...` or `t`)
+    #[arg(long)]
+    csv_time: Option<String>,
+
+    /// Text file value column names or indices (comma separated, default: all others)
+    #[arg(long, value_delimiter = ',')]
+    csv_columns: Vec<String>,
+
+    /// Text file sample rate in Hertz (default: from the time column)
+    #[arg(long)]
+    csv_fs: Option<f64>,
+
+    /// Names of several files or endpoints, used as trace name prefixes (comma separated)
+    ///
+    /// Defaults to `dev1`, `dev2`, ...
+    #[arg(long, value_delimiter = ',')]
+    origin: Vec<String>,
+
+    /// Frame size in headerless files (8 + n_batches*n_channel*batch_size)
+    ///
+    /// Default: detected from the frame headers
+    #[arg(long)]
+    frame_size: Option<usize>,
+
+    /// On a file, wrap around and repeat
+    #[arg(long)]
+    repeat: bool,
+
+    /// Raw file of interleaved samples, see the `--raw-*` options
+    #[arg(short, long)]
+    raw: Option<String>,
+
+    /// Raw sample type
+    #[arg(long, value_enum, default_value_t = SampleType::F32)]
+    raw_type: SampleType,
+
+    /// Raw sample byte order
+    #[arg(long, value_enum, default_value_t = Endian::Little)]
+    raw_endian: Endian,
+
+    /// Number of interleaved raw channels
+    #[arg(long, default_value_t = 1)]
+    raw_channels: usize,
+
+    /// Physical value of one unit of the raw samples
+    #[arg(long, default_value_t = 1.0)]
+    raw_scale: f32,
+
+    /// Raw channel samples are complex I/Q pairs
+    #[arg(long)]
+    raw_iq: bool,
+
+    /// Power law noise with psd f^noise.
+    #[arg(short, long)]
+    noise: Option<i32>,
+
+    /// Delta sigma modulator (MASH-1-1-1) with given frequency calibration marker
+    #[arg(long)]
+    dsm: Option<u32>,
+
+    /// FLS phase counts per turn (firmware `phase_scale`)
+    #[arg(long, default_value_t = (1u64 << 16) as f64)]
+    phase_scale: f64,
+
+    /// FLS phase: remove the initial offset and unwrap
+    #[arg(long)]
+    phase_unwrap: bool,
+
+    /// UDP socket receive buffer size in bytes
+    #[arg(long, default_value_t = 1 << 20)]
+    recv_buffer: usize,
+
+    /// Maximum number of UDP datagrams received per system call (Linux)
+    #[arg(long, default_value_t = 32)]
+    batch: usize,
+
+    /// Only accept UDP frames from these sender addresses (comma separated)
+    #[arg(long, value_delimiter = ',')]
+    sender: Vec<std::net::IpAddr>,
+
+    /// Demultiplex UDP senders, prefixing trace names with the sender address
+    #[arg(long)]
+    demux: bool,
+
+    /// Put frames back in sequence order within a window of this many frames (0: off)
+    #[arg(long, default_value_t = 0)]
+    reorder: usize,
+
+    /// Samples of lost batches
+    #[arg(long, value_enum, default_value_t = Gap::Drop)]
+    gap: Gap,
+
+    /// Decode frames with this format ID as demodulated lockin in-phase/quadrature data
+    #[arg(long)]
+    lockin: Option<u8>,
+
+    /// Lockin batch size in ADC samples (the sample rate divider of the lockin traces)
+    #[arg(long, default_value_t = 8)]
+    lockin_batch_size: usize,
+
+    /// Only forward the traces with these names, in this order (comma separated)
+    #[arg(long, value_delimiter = ',')]
+    traces: Vec<String>,
+}
+
+/// A source of trace data
+///
+/// Implemented by the sources in this module and by [`Source`] which selects
+/// one of them from [`SourceOpts`].
+/// Implement this to feed the analysis tools from other inputs.
+pub trait TraceSource {
+    /// Get the next block of traces
+    ///
+    /// The trace buffers are reused, see [`Trace::prepare()`].
+    fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()>;
+
+    /// Receive timestamp of the most recent block, if known
+    fn timestamp(&self) -> Option<SystemTime> {
+        None
+    }
+
+    /// Base sample rate in Hertz, if known
+    fn sample_rate(&self) -> Option<f64> {
+        None
+    }
+
+    /// Finish acquisition and report statistics
+    fn finish(&self) {}
+}
+
+impl<T: TraceSource + ?Sized> TraceSource for Box<T> {
+    fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()> {
+        (**self).get(traces)
+    }
+
+    fn timestamp(&self) -> Option<SystemTime> {
+        (**self).timestamp()
+    }
+
+    fn sample_rate(&self) -> Option<f64> {
+        (**self).sample_rate()
+    }
+
+    fn finish(&self) {
+        (**self).finish()
+    }
+}
+
+/// Whether a [`TraceSource::get()`] error ends the source (e.g. the end of a file)
+///
+/// I/O errors other than timeouts are terminal. Decoding errors are not.
+pub fn is_terminal(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<std::io::Error>().is_some_and(|e| {
+        !matches!(
+            e.kind(),
+            std::io::ErrorKind::WouldBlock
+                | std::io::ErrorKind::TimedOut
+                | std::io::ErrorKind::Interrupted
+        )
+    })
+}
+
+/// Largest gap in batches that is filled
+///
+/// Larger sequence jumps are taken as stream restarts.
+const MAX_GAP: u32 = 1 << 16;
+
+/// Frame decoding state
+#[derive(Debug, Default)]
+struct Decoder {
+    loss: Loss,
+    fls: [FlsPhase; 2],
+    reorder: Option<Reorder>,
+    gap: Gap,
+    // Lockin format ID and batch size
+    lockin: Option<(Format, usize)>,
+    // Trace lengths before the current frame
+    start: Vec<usize>,
+    // Metadata of the traces of the previous frame
+    meta: Vec<Trace>,
+    // Last value of each trace
+    last: Vec<Option<f32>>,
+    // Filled batches and samples
+    filled: (u64, u64),
+}
+
+impl Decoder {
+    /// Set the FLS phase scaling and unwrapping
+    fn set_fls_phase(&mut self, phase: FlsPhase) {
+        self.fls = [phase; 2];
+    }
+
+    /// Put frames back in sequence order within a window of frames
+    ///
+    /// A window of zero disables reordering.
+    fn set_reorder(&mut self, window: usize) {
+        self.reorder = (window > 0).then(|| Reorder::new(window));
+    }
+
+    /// Set the policy for the samples of lost batches
+    fn set_gap(&mut self, gap: Gap) {
+        self.gap = gap;
+    }
+
+    /// Decode frames with the given format ID as [`Lockin`] data
+    fn set_lockin(&mut self, format: Format, batch_size: usize) {
+        self.lockin = Some((format, batch_size));
+    }
+
+    /// A decoder with the same configuration
+    fn like(&self) -> Self {
+        Self {
+            fls: self.fls,
+            reorder: self.reorder.as_ref().map(|r| Reorder::new(r.window())),
+            gap: self.gap,
+            lockin: self.lockin,
+            ..Default::default()
+        }
+    }
+
+    /// Decode a frame into trace buffers and update loss statistics
+    ///
+    /// With reordering, the frame may be buffered and several frames may be decoded.
+    /// Returns whether traces were decoded.
+    fn decode(&mut self, buf: &[u8], traces: &mut Vec<Trace>) -> Result<bool> {
+        let Some(mut reorder) = self.reorder.take() else {
+            self.decode_frame(buf, traces, false)?;
+            return Ok(true);
+        };
+        let mut append = false;
+        let ret = reorder.push(buf, |frame| {
+            self.decode_frame(frame, traces, append)?;
+            append = true;
+            Result::<()>::Ok(())
+        });
+        self.reorder = Some(reorder);
+        ret.and(Ok(append))
+    }
+
+    /// Decode the frames remaining in the reorder buffer
+    ///
+    /// Returns whether traces were decoded.
+    fn flush(&mut self, traces: &mut Vec<Trace>) -> Result<bool> {
+        let Some(mut reorder) = self.reorder.take() else {
+            return Ok(false);
+        };
+        let mut append = false;
+        let ret = reorder.flush(|frame| {
+            self.decode_frame(frame, traces, append)?;
+            append = true;
+            Result::<()>::Ok(())
+        });
+        self.reorder = Some(reorder);
+        ret.and(Ok(append))
+    }
+
+    /// Decode a frame into trace buffers, fill gaps and update loss statistics
+    fn decode_frame(
+        &mut self,
+        buf: &[u8],
+        traces: &mut Vec<Trace>,
+        append: bool,
+    ) -> Result<Header> {
+        let (header, data) = Frame::split(buf)?;
+        let batches = header.batches as _;
+        self.start.clear();
+        if append {
+            self.start.extend(traces.iter().map(|t| t.data.len()));
+        }
+        let registry = Registry::global().read().unwrap();
+        match header.format {
+            // Unwrap the phase across frames unless the format was registered again
+            Format::Fls if registry.is_builtin(Format::Fls) => {
+                Fls::new(batches, data)?.traces_with(&mut self.fls, traces, append)?
+            }
+            format if self.lockin.is_some_and(|(f, _)| f == format) => {
+                let batch_size = self.lockin.unwrap().1;
+                Lockin::with_batch_size(batches, batch_size, data)?.traces_into(traces, append)?
+            }
+            format => registry.decode_into(format, batches, data, traces, append)?,
+        }
+        drop(registry);
+        let gap = self.loss.gap(&header);
+        self.loss.update(&header);
+        if self.gap != Gap::Drop && gap > 0 && gap <= MAX_GAP && batches > 0 {
+            for (i, t) in traces.iter_mut().enumerate() {
+                // Traces replaced by a metadata change are not continued
+                if !self.meta.get(i).is_some_and(|m| t.same_meta(m)) {
+                    continue;
+                }
+                let at = self.start.get(i).copied().unwrap_or_default();
+                let n = (t.data.len() - at) / batches * gap as usize;
+                let before = if at > 0 {
+                    Some(t.data[at - 1])
+                } else {
+                    self.last.get(i).copied().flatten()
+                };
+                self.gap.fill(&mut t.data, at, n, before);
+                self.filled.1 += n as u64;
+            }
+            self.filled.0 += gap as u64;
+        }
+        if traces.len() != self.meta.len()
+            || traces.iter().zip(&self.meta).any(|(t, m)| !t.same_meta(m))
+        {
+            self.meta = traces.iter().map(Trace::meta).collect();
+        }
+        self.last.clear();
+        self.last
+            .extend(traces.iter().map(|t| t.data.last().copied()));
+        Ok(header)
+    }
+
+    /// Report loss, reordering and gap filling statistics
+    fn analyze(&self) {
+        self.loss.analyze();
+        if let Some(reorder) = &self.reorder {
+            reorder.analyze();
+        }
+        if self.gap != Gap::Drop {
+            log::info!(
+                "Filled: {} batches ({} samples)",
+                self.filled.0,
+                self.filled.1
+            );
+        }
+    }
+}
+
+/// Traces of several origins
+///
+/// Assembles the union of the traces of all origins with names prefixed by the origin.
+/// Origins are ordered by their first data so that existing trace indices remain valid.
+#[derive(Debug, Default)]
+struct Union {
+    origins: Vec<Origin>,
+    order: Vec<usize>,
+}
+
+#[derive(Debug, Default)]
+struct Origin {
+    prefix: String,
+    // Traces as decoded
+    traces: Vec<Trace>,
+    // Metadata of the decoded traces
+    plain: Vec<Trace>,
+    // Metadata with prefixed names
+    meta: Vec<Trace>,
+}
+
+impl Union {
+    /// Add an origin and return its index
+    fn push(&mut self, prefix: String) -> usize {
+        self.origins.push(Origin {
+            prefix,
+            ..Default::default()
+        });
+        self.origins.len() - 1
+    }
+
+    /// Trace buffers of an origin
+    fn traces(&mut self, origin: usize) -> &mut Vec<Trace> {
+        &mut self.origins[origin].traces
+    }
+
+    /// Assemble the union with the data of an origin
+    ///
+    /// The traces of all other origins are empty.
+    fn merge(&mut self, origin: usize, traces: &mut Vec<Trace>) {
+        if !self.order.contains(&origin) {
+            self.order.push(origin);
+        }
+        let o = &mut self.origins[origin];
+        if o.traces.len() != o.plain.len()
+            || o.traces.iter().zip(&o.plain).any(|(t, p)| !t.same_meta(p))
+        {
+            o.plain = o.traces.iter().map(Trace::meta).collect();
+            o.meta = o
+                .traces
+                .iter()
+                .map(|t| Trace {
+                    name: format!("{}/{}", o.prefix, t.name).into(),
+                    ..t.meta()
+                })
+                .collect();
+        }
+        let ordered = || self.order.iter().map(|i| &self.origins[*i]);
+        Trace::prepare(traces, ordered().flat_map(|o| &o.meta), false);
+        let pos = self.order.iter().position(|i| *i == origin).unwrap();
+        let offset: usize = self.order[..pos]
+            .iter()
+            .map(|i| self.origins[*i].meta.len())
+            .sum();
+        for (t, o) in traces[offset..]
+            .iter_mut()
+            .zip(&self.origins[origin].traces)
+        {
+            t.data.extend_from_slice(&o.data);
+        }
+    }
+}
+
+/// The source configured by [`SourceOpts`]
+pub struct Source {
+    inner: Box<dyn TraceSource + Send>,
+    select: Vec<String>,
+    all: Vec<Trace>,
+}
+
+impl Source {
+    pub fn new(opts: SourceOpts) -> Result<Self> {
+        let phase = FlsPhase::new(opts.phase_scale, opts.phase_unwrap);
+        let file = |path: &String| -> Result<Box<dyn TraceSource + Send>> {
+            if WavSource::detect(path)? {
+                return Ok(Box::new(WavSource::new(path, opts.repeat)?));
+            }
+            let ext = std::path::Path::new(path).extension();
+            if ext.is_some_and(|e| {
+                ["csv", "tsv", "txt"]
+                    .iter()
+                    .any(|t| e.eq_ignore_ascii_case(t))
+            }) {
+                let format = TextFormat {
+                    delimiter: opts.csv_delimiter,
+                    time: opts.csv_time.clone(),
+                    columns: opts.csv_columns.clone(),
+                    sample_rate: opts.csv_fs,
+                };
+                return Ok(Box::new(TextSource::new(path, &format, opts.repeat)?));
+            }
+            let mut s = FileSource::new(path, opts.frame_size, opts.repeat)?;
+            s.set_fls_phase(phase);
+            s.set_reorder(opts.reorder);
+            s.set_gap(opts.gap);
+            if let Some(format) = opts.lockin {
+                s.set_lockin(format.into(), opts.lockin_batch_size);
+            }
+            s.set_filter(Filter {
+                port: Some(opts.port),
+                addr: (!opts.ip.is_unspecified()).then_some(opts.ip.into()),
+            });
+            Ok(Box::new(s))
+        };
+        let udp = |addr: SocketAddrV4| -> Result<Box<dyn TraceSource + Send>> {
+            let mut s = UdpSource::new(*addr.ip(), addr.port())?;
+            s.set_recv_buffer(opts.recv_buffer)?;
+            s.set_batch(opts.batch);
+            s.set_fls_phase(phase);
+            s.set_reorder(opts.reorder);
+            s.set_gap(opts.gap);
+            if let Some(format) = opts.lockin {
+                s.set_lockin(format.into(), opts.lockin_batch_size);
+            }
+            s.set_senders(opts.sender.clone());
+            s.set_demux(opts.demux);
+            Ok(Box::new(s))
+        };
+        let multi = |sources: Vec<Box<dyn TraceSource + Send>>| -> Box<dyn TraceSource + Send> {
+            let names = opts
+                .origin
+                .iter()
+                .cloned()
+                .chain((opts.origin.len() + 1..).map(|i| format!("dev{i}")));
+            Box::new(MultiSource::new(names.zip(sources).collect()))
+        };
+        let inner: Box<dyn TraceSource + Send> = if let Some(noise) = opts.noise {
+            Box::new(NoiseSource::new(noise))
+        } else if let [path] = &opts.file[..] {
+            file(path)?
+        } else if !opts.file.is_empty() {
+            multi(opts.file.iter().map(file).collect::<Result<_>>()?)
+        } else if let Some(raw) = &opts.raw {
+            let mut s = RawSource::new(raw, opts.repeat)?;
+            s.set_format(RawFormat {
+                sample: opts.raw_type,
+                endian: opts.raw_endian,
+                channels: opts.raw_channels,
+                scale: opts.raw_scale,
+                iq: opts.raw_iq,
+            })?;
+            Box::new(s)
+        } else if let Some(ftw) = opts.dsm {
+            Box::new(DsmSource::new(ftw))
+        } else if opts.endpoint.len() > 1 {
+            multi(
+                opts.endpoint
+                    .iter()
+                    .copied()
+                    .map(udp)
+                    .collect::<Result<_>>()?,
+            )
+        } else {
+            udp(opts
+                .endpoint
+                .first()
+                .copied()
+                .unwrap_or(SocketAddrV4::new(opts.ip, opts.port)))?
+        };
+        Ok(Self {
+            inner,
+            select: opts.traces,
+            all: vec![],
+        })
+    }
+}
+
+impl TraceSource for Source {
+    /// Get the next block of traces
+    ///
+    /// If a trace selection is configured, only the selected traces are returned.
+    fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()> {
+        if self.select.is_empty() {
+            return self.inner.get(traces);
+        }
+        let mut all = core::mem::take(&mut self.all);
+        let ret = self.inner.get(&mut all);
+        if ret.is_ok() {
+            let selected = || {
+                self.select
+                    .iter()
+                    .filter_map(|name| all.iter().find(|t| t.name == name.as_str()))
+            };
+            Trace::prepare(traces, selected(), false);
+            for (t, s) in traces.iter_mut().zip(selected()) {
+                t.data.extend_from_slice(&s.data);
+            }
+        }
+        self.all = all;
+        ret
+    }
+
+    fn timestamp(&self) -> Option<SystemTime> {
+        self.inner.timestamp()
+    }
+
+    fn sample_rate(&self) -> Option<f64> {
+        self.inner.sample_rate()
+    }
+
+    fn finish(&self) {
+        self.inner.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn union() {
+        let mut u = Union::default();
+        assert_eq!(u.push("a".into()), 0);
+        assert_eq!(u.push("b".into()), 1);
+        *u.traces(1) = vec![Trace::new("X", vec![1.0])];
+        let mut traces = vec![];
+        u.merge(1, &mut traces);
+        assert_eq!(traces, [Trace::new("b/X", vec![1.0])]);
+        *u.traces(0) = vec![Trace::new("X", vec![2.0]), Trace::new("Y", vec![3.0])];
+        u.merge(0, &mut traces);
+        assert_eq!(
+            traces,
+            [
+                Trace::new("b/X", vec![]),
+                Trace::new("a/X", vec![2.0]),
+                Trace::new("a/Y", vec![3.0]),
+            ]
+        );
+    }
+}
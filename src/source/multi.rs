@@ -0,0 +1,229 @@
+use super::{is_terminal, TraceSource, Union};
+use crate::Trace;
+use anyhow::Result;
+use std::{
+    io::ErrorKind,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+type Block = (usize, Vec<Trace>, Option<SystemTime>);
+
+type Worker = JoinHandle<Box<dyn TraceSource + Send>>;
+
+/// Traces from several sources at once
+///
+/// Each source is moved into its own thread and keeps its own state (e.g. loss statistics).
+/// The traces of all sources seen so far are returned with names prefixed by the
+/// source name (e.g. `dev1/ADC0`).
+/// Only the traces of the source of the most recent block contain data.
+///
+/// A source ends at its first terminal error (e.g. the end of a file), which is returned once.
+/// Once all sources have ended, [`TraceSource::get()`] returns
+/// [`ErrorKind::UnexpectedEof`].
+/// [`TraceSource::finish()`] stops all sources and reports their statistics.
+pub struct MultiSource {
+    names: Vec<String>,
+    workers: Mutex<Vec<Worker>>,
+    stop: Arc<AtomicBool>,
+    blocks: mpsc::Receiver<Result<Block>>,
+    recycle: Vec<mpsc::Sender<Vec<Trace>>>,
+    union: Union,
+    timestamp: Option<SystemTime>,
+    sample_rate: Option<f64>,
+}
+
+impl MultiSource {
+    /// Start reading from the named sources
+    pub fn new(sources: Vec<(String, Box<dyn TraceSource + Send>)>) -> Self {
+        let (send, blocks) = mpsc::sync_channel(sources.len());
+        let stop = Arc::new(AtomicBool::new(false));
+        let sample_rate = sources.iter().find_map(|(_, s)| s.sample_rate());
+        let mut union = Union::default();
+        let mut names = vec![];
+        let mut recycle = vec![];
+        let workers = sources
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, mut source))| {
+                union.push(name.clone());
+                names.push(name);
+                let (recycle_send, recycle_recv) = mpsc::channel();
+                recycle.push(recycle_send);
+                let send = send.clone();
+                let stop = stop.clone();
+                std::thread::spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        let mut traces = recycle_recv.try_recv().unwrap_or_default();
+                        let block = match source.get(&mut traces) {
+                            Ok(()) => Ok((i, traces, source.timestamp())),
+                            Err(e) if is_terminal(&e) => {
+                                send.send(Err(e)).ok();
+                                break;
+                            }
+                            Err(e) => Err(e),
+                        };
+                        if send.send(block).is_err() {
+                            break;
+                        }
+                    }
+                    source
+                })
+            })
+            .collect();
+        Self {
+            names,
+            workers: Mutex::new(workers),
+            stop,
+            blocks,
+            recycle,
+            union,
+            timestamp: None,
+            sample_rate,
+        }
+    }
+
+    /// Stop all sources and return them
+    fn stop(&self) -> Vec<(&str, Option<Box<dyn TraceSource + Send>>)> {
+        self.stop.store(true, Ordering::Relaxed);
+        let workers = core::mem::take(&mut *self.workers.lock().unwrap());
+        workers
+            .into_iter()
+            .zip(&self.names)
+            .map(|(worker, name)| {
+                // Unblock the worker if it waits for the channel
+                while !worker.is_finished() {
+                    while self.blocks.try_recv().is_ok() {}
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                let source = worker.join().ok();
+                if source.is_none() {
+                    log::warn!("Source {} panicked", name);
+                }
+                (name.as_str(), source)
+            })
+            .collect()
+    }
+}
+
+impl Drop for MultiSource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl TraceSource for MultiSource {
+    fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()> {
+        let (i, mut block, timestamp) = match self.blocks.recv_timeout(Duration::from_secs(1)) {
+            Ok(block) => block?,
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(std::io::Error::from(ErrorKind::TimedOut))?,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(std::io::Error::from(ErrorKind::UnexpectedEof))?
+            }
+        };
+        core::mem::swap(self.union.traces(i), &mut block);
+        self.recycle[i].send(block).ok();
+        self.timestamp = timestamp;
+        self.union.merge(i, traces);
+        Ok(())
+    }
+
+    fn timestamp(&self) -> Option<SystemTime> {
+        self.timestamp
+    }
+
+    /// Sample rate of the first source that knows it
+    fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+
+    fn finish(&self) {
+        for (name, source) in self.stop() {
+            if let Some(source) = source {
+                log::info!("Source {}", name);
+                source.finish();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::source::{DsmSource, NoiseSource};
+
+    #[test]
+    fn names() {
+        let mut source = MultiSource::new(vec![
+            ("a".into(), Box::new(NoiseSource::new(0))),
+            ("b".into(), Box::new(DsmSource::new(1 << 20))),
+        ]);
+        let mut traces = vec![];
+        while traces.len() < 2 {
+            source.get(&mut traces).unwrap();
+            assert_eq!(traces.iter().filter(|t| !t.data.is_empty()).count(), 1);
+        }
+        let mut names: Vec<_> = traces.iter().map(|t| t.name.as_ref()).collect();
+        names.sort();
+        assert_eq!(names, ["a/noise", "b/dsm"]);
+        source.finish();
+    }
+
+    #[derive(Debug)]
+    struct Finite(usize, Arc<AtomicBool>);
+
+    impl TraceSource for Finite {
+        fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()> {
+            if self.0 == 0 {
+                Err(std::io::Error::from(ErrorKind::UnexpectedEof))?
+            }
+            self.0 -= 1;
+            Trace::prepare(traces, [Trace::new("x", vec![])], false);
+            traces[0].data.push(self.0 as _);
+            Ok(())
+        }
+
+        fn sample_rate(&self) -> Option<f64> {
+            Some(1.0)
+        }
+
+        fn finish(&self) {
+            self.1.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn eof() {
+        let finished = [(); 2].map(|_| Arc::new(AtomicBool::new(false)));
+        let mut source = MultiSource::new(vec![
+            ("a".into(), Box::new(Finite(2, finished[0].clone()))),
+            ("b".into(), Box::new(Finite(3, finished[1].clone()))),
+        ]);
+        assert_eq!(source.sample_rate(), Some(1.0));
+        let mut traces = vec![];
+        let (mut ok, mut err) = (0, 0);
+        loop {
+            match source.get(&mut traces) {
+                Ok(()) => ok += 1,
+                Err(e)
+                    if e.downcast_ref::<std::io::Error>().unwrap().kind()
+                        == ErrorKind::UnexpectedEof =>
+                {
+                    err += 1;
+                    if err > 2 {
+                        break;
+                    }
+                }
+                Err(e) => panic!("{e}"),
+            }
+        }
+        // One EOF per source and one for all
+        assert_eq!((ok, err), (5, 3));
+        source.finish();
+        assert!(finished.iter().all(|f| f.load(Ordering::Relaxed)));
+    }
+}
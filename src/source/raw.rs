@@ -0,0 +1,225 @@
+use super::TraceSource;
+use crate::Trace;
+use anyhow::Result;
+use std::{
+    fs::File,
+    io::{BufReader, ErrorKind, Read, Seek},
+    path::Path,
+};
+
+/// Raw sample type
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SampleType {
+    I16,
+    I32,
+    #[default]
+    F32,
+    F64,
+}
+
+impl SampleType {
+    /// Size in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Self::I16 => 2,
+            Self::I32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// Convert one sample
+    fn read(&self, b: &[u8], endian: Endian) -> f32 {
+        macro_rules! read {
+            ($t:ty) => {{
+                let b = b.try_into().unwrap();
+                (match endian {
+                    Endian::Little => <$t>::from_le_bytes(b),
+                    Endian::Big => <$t>::from_be_bytes(b),
+                }) as f32
+            }};
+        }
+        match self {
+            Self::I16 => read!(i16),
+            Self::I32 => read!(i32),
+            Self::F32 => read!(f32),
+            Self::F64 => read!(f64),
+        }
+    }
+}
+
+/// Byte order
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+/// Raw file sample layout
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RawFormat {
+    /// Sample type
+    pub sample: SampleType,
+    /// Byte order
+    pub endian: Endian,
+    /// Number of interleaved channels
+    pub channels: usize,
+    /// Physical value of one unit of the raw samples
+    pub scale: f32,
+    /// Each channel sample is a complex I/Q pair
+    pub iq: bool,
+}
+
+impl Default for RawFormat {
+    fn default() -> Self {
+        Self {
+            sample: SampleType::F32,
+            endian: Endian::Little,
+            channels: 1,
+            scale: 1.0,
+            iq: false,
+        }
+    }
+}
+
+impl RawFormat {
+    /// Number of samples in one sample frame (all channels)
+    fn width(&self) -> usize {
+        self.channels * (1 + self.iq as usize)
+    }
+
+    /// Trace metadata
+    ///
+    /// A single real channel is named `raw`, several are `raw0`, `raw1`, ...
+    /// I/Q channels are `I`/`Q` or `I0`, `Q0`, `I1`, ...
+    fn traces(&self) -> Vec<Trace> {
+        let index = |i: usize| {
+            if self.channels == 1 {
+                String::new()
+            } else {
+                i.to_string()
+            }
+        };
+        (0..self.channels)
+            .flat_map(|i| {
+                if self.iq {
+                    vec![format!("I{}", index(i)), format!("Q{}", index(i))]
+                } else {
+                    vec![format!("raw{}", index(i))]
+                }
+            })
+            .map(|name| Trace::new(name, vec![]).with_unit("", self.scale))
+            .collect()
+    }
+}
+
+/// Traces from a raw file of interleaved samples
+#[derive(Debug)]
+pub struct RawSource {
+    reader: BufReader<File>,
+    repeat: bool,
+    format: RawFormat,
+    meta: Vec<Trace>,
+    buf: Vec<u8>,
+    fill: usize,
+}
+
+impl RawSource {
+    /// Open a raw file of little endian f32 samples of a single channel.
+    ///
+    /// With `repeat`, wrap around at the end of the file.
+    pub fn new(path: impl AsRef<Path>, repeat: bool) -> Result<Self> {
+        let mut s = Self {
+            reader: BufReader::with_capacity(1 << 20, File::open(path)?),
+            repeat,
+            format: RawFormat::default(),
+            meta: vec![],
+            buf: vec![],
+            fill: 0,
+        };
+        s.set_format(RawFormat::default())?;
+        Ok(s)
+    }
+
+    /// Set the sample layout
+    pub fn set_format(&mut self, format: RawFormat) -> Result<()> {
+        anyhow::ensure!(format.channels > 0, "Invalid channel count");
+        let frame = format.width() * format.sample.size();
+        self.buf = vec![0; frame * (2048 / frame).max(1)];
+        self.fill = 0;
+        self.meta = format.traces();
+        self.format = format;
+        Ok(())
+    }
+}
+
+impl TraceSource for RawSource {
+    fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()> {
+        let size = self.format.sample.size();
+        let frame = self.format.width() * size;
+        loop {
+            let len = self.reader.read(&mut self.buf[self.fill..])?;
+            if len == 0 {
+                if !self.repeat {
+                    Err(std::io::Error::from(ErrorKind::UnexpectedEof))?;
+                }
+                // Drop a partial sample frame at the end
+                self.fill = 0;
+                self.reader.seek(std::io::SeekFrom::Start(0))?;
+                continue;
+            }
+            self.fill += len;
+            let end = self.fill / frame * frame;
+            if end == 0 {
+                continue;
+            }
+            Trace::prepare(traces, &self.meta, false);
+            for f in self.buf[..end].chunks_exact(frame) {
+                for (t, b) in traces.iter_mut().zip(f.chunks_exact(size)) {
+                    t.data
+                        .push(self.format.sample.read(b, self.format.endian) * self.format.scale);
+                }
+            }
+            self.buf.copy_within(end..self.fill, 0);
+            self.fill -= end;
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("raw.bin");
+        let data: Vec<u8> = [1i16, -2, 3, -4, 5]
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .collect();
+        std::fs::write(&path, data).unwrap();
+
+        let mut source = RawSource::new(&path, false).unwrap();
+        source
+            .set_format(RawFormat {
+                sample: SampleType::I16,
+                endian: Endian::Big,
+                channels: 1,
+                scale: 0.5,
+                iq: true,
+            })
+            .unwrap();
+        let mut traces = vec![];
+        source.get(&mut traces).unwrap();
+        assert_eq!(traces[0].name, "I");
+        assert_eq!(traces[0].data, [0.5, 1.5]);
+        assert_eq!(traces[1].name, "Q");
+        assert_eq!(traces[1].data, [-1.0, -2.0]);
+        assert!(source.get(&mut traces).is_err());
+    }
+}
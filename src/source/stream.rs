@@ -0,0 +1,191 @@
+use super::{is_terminal, TraceSource, UdpSource};
+use crate::Trace;
+use anyhow::Result;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::SystemTime,
+};
+use tokio::{io::Interest, net::UdpSocket, sync::mpsc, task::JoinHandle};
+
+/// Asynchronous UDP source
+///
+/// Shares sender filtering, demultiplexing, decoding and loss statistics with [`UdpSource`].
+/// The receive futures are cancel safe.
+#[derive(Debug)]
+pub struct AsyncUdpSource {
+    inner: UdpSource,
+    io: UdpSocket,
+}
+
+impl AsyncUdpSource {
+    /// Receive asynchronously on a configured [`UdpSource`]
+    ///
+    /// Must be called within a Tokio runtime.
+    /// The socket timeout of the source no longer applies.
+    pub fn new(inner: UdpSource) -> std::io::Result<Self> {
+        let socket = inner.try_clone_socket()?;
+        socket.set_nonblocking(true)?;
+        let io = UdpSocket::from_std(socket.into())?;
+        Ok(Self { inner, io })
+    }
+
+    /// Get the traces of the next frame
+    ///
+    /// See [`TraceSource::get()`].
+    pub async fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()> {
+        let mut buf = [0u8; 2048];
+        loop {
+            if let Some(len) = self.inner.pending(&mut buf) {
+                if self.inner.decode(&buf[..len], traces)? {
+                    return Ok(());
+                }
+                continue;
+            }
+            self.io
+                .async_io(Interest::READABLE, || self.inner.fill())
+                .await?;
+        }
+    }
+
+    /// Receive timestamp of the most recent frame
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        self.inner.timestamp()
+    }
+
+    /// Report loss statistics
+    pub fn finish(&self) {
+        self.inner.finish()
+    }
+
+    /// Stream the traces of each frame
+    ///
+    /// Must be called within a Tokio runtime.
+    /// The source is finished when the stream is dropped.
+    pub fn into_stream(self) -> TraceStream {
+        // Finishes the source when the task ends, also when it is aborted
+        struct Finish(AsyncUdpSource);
+
+        impl Drop for Finish {
+            fn drop(&mut self) {
+                self.0.finish();
+            }
+        }
+
+        let (send, recv) = mpsc::channel(1);
+        let mut source = Finish(self);
+        let task = tokio::spawn(async move {
+            loop {
+                let mut traces = vec![];
+                let ret = source.0.get(&mut traces).await.map(|()| traces);
+                let terminal = ret.as_ref().is_err_and(is_terminal);
+                if send.send(ret).await.is_err() || terminal {
+                    break;
+                }
+            }
+        });
+        TraceStream { recv, task }
+    }
+}
+
+/// Asynchronous stream of traces
+///
+/// Dropping the stream cancels acquisition and finishes the source.
+#[derive(Debug)]
+pub struct TraceStream {
+    recv: mpsc::Receiver<Result<Vec<Trace>>>,
+    task: JoinHandle<()>,
+}
+
+impl TraceStream {
+    /// Stream from a blocking source (e.g. a [`super::FileSource`])
+    ///
+    /// The source is read on the blocking thread pool of the Tokio runtime.
+    /// The stream ends after a terminal error of the source (e.g. the end of a file),
+    /// see [`is_terminal()`].
+    /// When the stream is dropped, the source is finished after its current block.
+    pub fn blocking(mut source: impl TraceSource + Send + 'static) -> Self {
+        let (send, recv) = mpsc::channel(1);
+        let handle = tokio::task::spawn_blocking(move || {
+            loop {
+                let mut traces = vec![];
+                let ret = source.get(&mut traces).map(|()| traces);
+                let terminal = ret.as_ref().is_err_and(is_terminal);
+                if send.blocking_send(ret).is_err() || terminal {
+                    break;
+                }
+            }
+            drop(send);
+            source.finish();
+        });
+        let task = tokio::spawn(async move {
+            handle.await.ok();
+        });
+        Self { recv, task }
+    }
+
+    /// The traces of the next block
+    pub async fn next(&mut self) -> Option<Result<Vec<Trace>>> {
+        self.recv.recv().await
+    }
+}
+
+impl futures_core::Stream for TraceStream {
+    type Item = Result<Vec<Trace>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.recv.poll_recv(cx)
+    }
+}
+
+impl Drop for TraceStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        source::{FileSource, NoiseSource},
+        PayloadBuilder, ThermostatEemBuilder,
+    };
+    use std::net::Ipv4Addr;
+
+    #[tokio::test]
+    async fn udp() {
+        let source = UdpSource::new(Ipv4Addr::LOCALHOST, 0).unwrap();
+        let addr = source.local_addr().unwrap();
+        let mut stream = AsyncUdpSource::new(source).unwrap().into_stream();
+        let mut frame = ThermostatEemBuilder::default();
+        frame.push([1.0; 20]);
+        std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .send_to(&frame.frame(0), addr)
+            .unwrap();
+        let traces = stream.next().await.unwrap().unwrap();
+        assert_eq!(traces[0].data, [1.0]);
+    }
+
+    #[tokio::test]
+    async fn blocking_eof() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frames.bin");
+        let mut frame = ThermostatEemBuilder::default();
+        frame.push([1.0; 20]);
+        std::fs::write(&path, frame.frame(0)).unwrap();
+        let mut stream = TraceStream::blocking(FileSource::new(&path, None, false).unwrap());
+        let traces = stream.next().await.unwrap().unwrap();
+        assert_eq!(traces[0].data, [1.0]);
+        assert!(stream.next().await.unwrap().is_err());
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn blocking() {
+        let mut stream = TraceStream::blocking(NoiseSource::new(0));
+        let traces = stream.next().await.unwrap().unwrap();
+        assert_eq!(traces[0].data.len(), 4096);
+    }
+}
@@ -0,0 +1,75 @@
+use super::TraceSource;
+use crate::Trace;
+use anyhow::Result;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+/// Power law noise
+#[derive(Debug)]
+pub struct NoiseSource {
+    rng: SmallRng,
+    diff: bool,
+    state: Vec<f32>,
+}
+
+impl NoiseSource {
+    /// Noise with PSD `f^exponent`
+    pub fn new(exponent: i32) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(0x7654321),
+            diff: exponent > 0,
+            state: vec![0.0; exponent.unsigned_abs() as _],
+        }
+    }
+}
+
+impl TraceSource for NoiseSource {
+    fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()> {
+        let Self { rng, diff, state } = self;
+        Trace::prepare(traces, [Trace::new("noise", vec![])], false);
+        traces[0].data.extend(
+            rng.sample_iter(rand::distributions::Open01)
+                .map(|mut x| {
+                    x = (x - 0.5) * 12.0f32.sqrt(); // zero mean, RMS = 1
+                    state.iter_mut().fold(x, |mut x, s| {
+                        (x, *s) = if *diff { (x - *s, x) } else { (*s, x + *s) };
+                        x
+                    })
+                })
+                .take(4096),
+        );
+        Ok(())
+    }
+}
+
+/// Delta sigma modulator (MASH-1-1-1) output
+#[derive(Debug)]
+pub struct DsmSource {
+    dsm: idsp::Dsm<3>,
+    x: u32,
+    ftw: u32,
+}
+
+impl DsmSource {
+    /// Modulate a sine with the given frequency tuning word as a calibration marker
+    pub fn new(ftw: u32) -> Self {
+        Self {
+            dsm: Default::default(),
+            x: 1,
+            ftw,
+        }
+    }
+}
+
+impl TraceSource for DsmSource {
+    fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()> {
+        let Self { dsm, x, ftw } = self;
+        Trace::prepare(traces, [Trace::new("dsm", vec![])], false);
+        traces[0].data.extend((0..4096).map(|_| {
+            const M: f32 = (1u64 << 32) as f32;
+            let xi = (((*x as f32 * (core::f32::consts::TAU / M)).sin() * 0.4999 + 0.5) * M) as u32;
+            *x = x.wrapping_add(*ftw);
+            dsm.update(xi) as f32 - 0.5
+        }));
+        Ok(())
+    }
+}
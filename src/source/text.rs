@@ -0,0 +1,286 @@
+use super::TraceSource;
+use crate::Trace;
+use anyhow::{Context, Result};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, ErrorKind, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Delimited text layout
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextFormat {
+    /// Field delimiter
+    ///
+    /// Detected from the first line if not given: tab, comma, semicolon or whitespace.
+    pub delimiter: Option<char>,
+    /// Time column name or index
+    ///
+    /// Defaults to the first column if its name starts with `time` or is `t`.
+    pub time: Option<String>,
+    /// Value column names or indices, in this order
+    ///
+    /// Defaults to all columns but the time column.
+    pub columns: Vec<String>,
+    /// Sample rate in Hertz
+    ///
+    /// Defaults to the mean rate of the time column over the first rows.
+    pub sample_rate: Option<f64>,
+}
+
+/// Rows used to determine the sample rate
+const RATE_ROWS: usize = 1000;
+
+/// Rows per block
+const BLOCK_ROWS: usize = 1024;
+
+/// Traces from a delimited text (CSV/TSV) file
+///
+/// The file is read row by row. Empty lines and lines starting with `#` are skipped.
+/// If the first row is not numeric, it names the columns, otherwise
+/// the columns are named `col0`, `col1`, ...
+#[derive(Debug)]
+pub struct TextSource {
+    reader: BufReader<File>,
+    start: u64,
+    // Line number before the first data row
+    start_line: usize,
+    line: String,
+    line_number: usize,
+    delimiter: Option<char>,
+    time: Option<usize>,
+    columns: Vec<usize>,
+    meta: Vec<Trace>,
+    sample_rate: Option<f64>,
+    repeat: bool,
+}
+
+impl TextSource {
+    /// Open a text file.
+    ///
+    /// With `repeat`, wrap around at the end of the file.
+    pub fn new(path: impl AsRef<Path>, format: &TextFormat, repeat: bool) -> Result<Self> {
+        let mut reader = BufReader::with_capacity(1 << 20, File::open(path)?);
+        let mut line = String::new();
+        let mut start = 0;
+        let mut start_line = 0;
+        loop {
+            line.clear();
+            anyhow::ensure!(reader.read_line(&mut line)? > 0, "No data");
+            if !skip(&line) {
+                break;
+            }
+            start = reader.stream_position()?;
+            start_line += 1;
+        }
+        let delimiter = format
+            .delimiter
+            .or_else(|| ['\t', ',', ';'].into_iter().find(|d| line.contains(*d)));
+        let first: Vec<_> = fields(&line, delimiter).collect();
+        let names: Vec<String> = if first.iter().all(|f| f.parse::<f64>().is_ok()) {
+            (0..first.len()).map(|i| format!("col{i}")).collect()
+        } else {
+            start = reader.stream_position()?;
+            start_line += 1;
+            first.iter().map(|f| f.to_string()).collect()
+        };
+        let column = |c: &String| -> Result<usize> {
+            names
+                .iter()
+                .position(|n| n == c)
+                .or_else(|| c.parse().ok().filter(|i| *i < names.len()))
+                .with_context(|| format!("Unknown column {c}"))
+        };
+        let time = match &format.time {
+            Some(t) => Some(column(t)?),
+            None => names
+                .first()
+                .filter(|n| {
+                    let n = n.to_lowercase();
+                    n.starts_with("time") || n == "t"
+                })
+                .map(|_| 0),
+        };
+        let columns: Vec<_> = if format.columns.is_empty() {
+            (0..names.len()).filter(|i| Some(*i) != time).collect()
+        } else {
+            format.columns.iter().map(column).collect::<Result<_>>()?
+        };
+        anyhow::ensure!(!columns.is_empty(), "No value columns");
+        let meta = columns
+            .iter()
+            .map(|i| Trace::new(names[*i].clone(), vec![]))
+            .collect();
+        let mut s = Self {
+            reader,
+            start,
+            start_line,
+            line,
+            line_number: start_line,
+            delimiter,
+            time,
+            columns,
+            meta,
+            sample_rate: format.sample_rate,
+            repeat,
+        };
+        s.rewind()?;
+        if s.sample_rate.is_none() && s.time.is_some() {
+            s.sample_rate = s.measure_rate()?;
+            s.rewind()?;
+        }
+        log::info!(
+            "Text: columns {:?}, time {:?}, sample rate {:?}",
+            s.meta.iter().map(|t| &t.name).collect::<Vec<_>>(),
+            s.time.map(|i| &names[i]),
+            s.sample_rate
+        );
+        Ok(s)
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        self.reader.seek(SeekFrom::Start(self.start))?;
+        self.line_number = self.start_line;
+        Ok(())
+    }
+
+    /// Read the next data row into `self.line`, false at the end of the file
+    fn next_row(&mut self) -> Result<bool> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(false);
+            }
+            self.line_number += 1;
+            if !skip(&self.line) {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Mean sample rate of the time column over the first rows
+    fn measure_rate(&mut self) -> Result<Option<f64>> {
+        let time = self.time.unwrap();
+        let mut t = vec![];
+        while t.len() < RATE_ROWS && self.next_row()? {
+            let f = fields(&self.line, self.delimiter).nth(time);
+            t.push(parse::<f64>(f, self.line_number)?);
+        }
+        let [first, .., last] = t[..] else {
+            return Ok(None);
+        };
+        let dt = (last - first) / (t.len() - 1) as f64;
+        let jitter = t
+            .windows(2)
+            .map(|w| ((w[1] - w[0]) / dt - 1.0).abs())
+            .fold(0.0, f64::max);
+        if jitter > 0.01 {
+            log::warn!("Time column not uniform: {:.1} % deviation", jitter * 100.0);
+        }
+        Ok((dt > 0.0).then(|| 1.0 / dt))
+    }
+}
+
+/// Whether a line has no data
+fn skip(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+/// Fields of a line, trimmed and unquoted
+fn fields(line: &str, delimiter: Option<char>) -> Box<dyn Iterator<Item = &str> + '_> {
+    fn unquote(f: &str) -> &str {
+        let f = f.trim();
+        f.strip_prefix('"')
+            .and_then(|f| f.strip_suffix('"'))
+            .unwrap_or(f)
+    }
+    match delimiter {
+        Some(d) => Box::new(line.trim_end_matches(['\r', '\n']).split(d).map(unquote)),
+        None => Box::new(line.split_whitespace().map(unquote)),
+    }
+}
+
+/// Parse a field, with the line number for errors
+fn parse<T: std::str::FromStr>(field: Option<&str>, line: usize) -> Result<T> {
+    let field = field.with_context(|| format!("Line {line}: missing column"))?;
+    field
+        .parse()
+        .ok()
+        .with_context(|| format!("Line {line}: invalid number `{field}`"))
+}
+
+impl TraceSource for TextSource {
+    fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()> {
+        Trace::prepare(traces, &self.meta, false);
+        for _ in 0..BLOCK_ROWS {
+            if !self.next_row()? {
+                if !traces[0].data.is_empty() {
+                    break;
+                }
+                if !self.repeat {
+                    Err(std::io::Error::from(ErrorKind::UnexpectedEof))?;
+                }
+                self.rewind()?;
+                anyhow::ensure!(self.next_row()?, "No data");
+            }
+            let row: Vec<_> = fields(&self.line, self.delimiter).collect();
+            for (t, i) in traces.iter_mut().zip(&self.columns) {
+                t.data.push(parse(row.get(*i).copied(), self.line_number)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sample rate as given or from the time column
+    fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("text.csv");
+        std::fs::write(
+            &path,
+            "# exported\nTime (s),\"A\",B\n0.0,1,2\n\n0.5,3,4\r\n1.0,5,6\n",
+        )
+        .unwrap();
+
+        let format = TextFormat {
+            columns: vec!["B".into(), "1".into()],
+            ..Default::default()
+        };
+        let mut source = TextSource::new(&path, &format, true).unwrap();
+        assert_eq!(source.sample_rate(), Some(2.0));
+        let mut traces = vec![];
+        source.get(&mut traces).unwrap();
+        assert_eq!(traces[0].name, "B");
+        assert_eq!(traces[0].data, [2.0, 4.0, 6.0]);
+        assert_eq!(traces[1].name, "A");
+        assert_eq!(traces[1].data, [1.0, 3.0, 5.0]);
+        source.get(&mut traces).unwrap();
+        assert_eq!(traces[0].data, [2.0, 4.0, 6.0]);
+    }
+
+    #[test]
+    fn tsv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("text.tsv");
+        std::fs::write(&path, "1\t2\n3\t4\n").unwrap();
+
+        let mut source = TextSource::new(&path, &TextFormat::default(), false).unwrap();
+        assert_eq!(source.sample_rate(), None);
+        let mut traces = vec![];
+        source.get(&mut traces).unwrap();
+        assert_eq!(traces[1].name, "col1");
+        assert_eq!(traces[1].data, [2.0, 4.0]);
+        assert!(source.get(&mut traces).is_err());
+    }
+}
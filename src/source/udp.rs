@@ -0,0 +1,555 @@
+use super::{Decoder, TraceSource, Union};
+use crate::{FlsPhase, Format, Frame, Gap, Header, Trace};
+use anyhow::Result;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, SystemTime},
+};
+
+/// Per-sender decoding state
+#[derive(Debug, Default)]
+struct Demux {
+    senders: Vec<IpAddr>,
+    decoders: Vec<Decoder>,
+    union: Union,
+}
+
+impl Demux {
+    /// Index of the sender, adding it if new
+    fn index(&mut self, sender: IpAddr, template: &Decoder) -> usize {
+        if let Some(i) = self.senders.iter().position(|s| *s == sender) {
+            return i;
+        }
+        log::info!("New sender: {}", sender);
+        self.senders.push(sender);
+        self.decoders.push(template.like());
+        self.union.push(sender.to_string())
+    }
+}
+
+/// Maximum datagram size
+const MAX_DATAGRAM: usize = 2048;
+
+/// Datagrams received in one system call
+#[derive(Debug)]
+struct Batch {
+    buf: Vec<u8>,
+    datagrams: Vec<(usize, Option<SocketAddr>, Option<SystemTime>)>,
+    pos: usize,
+    #[cfg(target_os = "linux")]
+    headers: Headers,
+}
+
+impl Batch {
+    fn new(size: usize) -> Self {
+        let size = size.max(1);
+        let buf = vec![0; size * MAX_DATAGRAM];
+        #[cfg(target_os = "linux")]
+        let (headers, buf) = {
+            let mut buf = buf;
+            (Headers::new(&mut buf), buf)
+        };
+        Self {
+            #[cfg(target_os = "linux")]
+            headers,
+            buf,
+            datagrams: Vec::with_capacity(size),
+            pos: 0,
+        }
+    }
+
+    /// The next pending datagram
+    fn next(&mut self) -> Option<(&[u8], Option<SocketAddr>, Option<SystemTime>)> {
+        let (len, sender, timestamp) = *self.datagrams.get(self.pos)?;
+        let data = &self.buf[self.pos * MAX_DATAGRAM..][..len];
+        self.pos += 1;
+        Some((data, sender, timestamp))
+    }
+}
+
+/// Message headers for `recvmmsg`
+///
+/// Allocated once per batch. The headers point to the name and control buffers here
+/// and to the datagram buffer of the batch. These heap buffers are never resized.
+#[cfg(target_os = "linux")]
+struct Headers {
+    names: Vec<libc::sockaddr_storage>,
+    // Only referenced through the headers
+    _control: Vec<[u64; 8]>,
+    _iov: Vec<libc::iovec>,
+    msgs: Vec<libc::mmsghdr>,
+}
+
+// SAFETY: the pointers only refer to heap buffers owned by the batch
+#[cfg(target_os = "linux")]
+unsafe impl Send for Headers {}
+
+#[cfg(target_os = "linux")]
+impl core::fmt::Debug for Headers {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Headers")
+            .field("size", &self.msgs.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Headers {
+    fn new(buf: &mut [u8]) -> Self {
+        let size = buf.len() / MAX_DATAGRAM;
+        // SAFETY: sockaddr_storage is plain old data
+        let mut names: Vec<libc::sockaddr_storage> = vec![unsafe { core::mem::zeroed() }; size];
+        // u64 for cmsghdr alignment
+        let mut control = vec![[0u64; 8]; size];
+        let mut iov: Vec<_> = buf
+            .chunks_exact_mut(MAX_DATAGRAM)
+            .map(|buf| libc::iovec {
+                iov_base: buf.as_mut_ptr().cast(),
+                iov_len: buf.len(),
+            })
+            .collect();
+        let msgs = names
+            .iter_mut()
+            .zip(control.iter_mut())
+            .zip(iov.iter_mut())
+            .map(|((name, control), iov)| {
+                // SAFETY: mmsghdr is plain old data
+                let mut msg: libc::mmsghdr = unsafe { core::mem::zeroed() };
+                msg.msg_hdr.msg_name = (name as *mut libc::sockaddr_storage).cast();
+                msg.msg_hdr.msg_iov = iov;
+                msg.msg_hdr.msg_iovlen = 1;
+                msg.msg_hdr.msg_control = control.as_mut_ptr().cast();
+                msg
+            })
+            .collect();
+        Self {
+            names,
+            _control: control,
+            _iov: iov,
+            msgs,
+        }
+    }
+
+    /// Reset the lengths changed by the kernel
+    fn reset(&mut self) {
+        for msg in self.msgs.iter_mut() {
+            msg.msg_len = 0;
+            msg.msg_hdr.msg_namelen = core::mem::size_of::<libc::sockaddr_storage>() as _;
+            msg.msg_hdr.msg_controllen = core::mem::size_of::<[u64; 8]>() as _;
+            msg.msg_hdr.msg_flags = 0;
+        }
+    }
+}
+
+/// Stream frames received over UDP
+#[derive(Debug)]
+pub struct UdpSource {
+    socket: Socket,
+    batch: Batch,
+    decoder: Decoder,
+    timestamp: Option<SystemTime>,
+    sender: Option<SocketAddr>,
+    allow: Vec<IpAddr>,
+    demux: Option<Demux>,
+}
+
+impl UdpSource {
+    /// Bind to the given local IP and port.
+    ///
+    /// Multicast addresses are joined.
+    pub fn new(ip: Ipv4Addr, port: u16) -> Result<Self> {
+        log::info!("Binding to {}:{}", ip, port);
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_read_timeout(Some(Duration::from_millis(1000)))?;
+        socket.set_recv_buffer_size(1 << 20)?;
+        socket.set_reuse_address(true)?;
+        if ip.is_multicast() {
+            socket.join_multicast_v4(&ip, &Ipv4Addr::UNSPECIFIED)?;
+        }
+        #[cfg(windows)]
+        let bind = Ipv4Addr::UNSPECIFIED;
+        #[cfg(not(windows))]
+        let bind = ip;
+        socket.bind(&SocketAddr::new(bind.into(), port).into())?;
+        enable_timestamps(&socket)?;
+        Ok(Self {
+            socket,
+            batch: Batch::new(32),
+            decoder: Decoder::default(),
+            timestamp: None,
+            sender: None,
+            allow: vec![],
+            demux: None,
+        })
+    }
+
+    /// Set the FLS phase scaling and unwrapping
+    pub fn set_fls_phase(&mut self, phase: FlsPhase) {
+        self.decoder.set_fls_phase(phase);
+    }
+
+    /// Put frames back in sequence order within a window of frames
+    ///
+    /// A window of zero disables reordering.
+    /// Raw frames from [`UdpSource::recv()`] are not reordered.
+    pub fn set_reorder(&mut self, window: usize) {
+        self.decoder.set_reorder(window);
+    }
+
+    /// Set the policy for the samples of lost batches
+    pub fn set_gap(&mut self, gap: Gap) {
+        self.decoder.set_gap(gap);
+    }
+
+    /// Decode frames with the given format ID as [`crate::Lockin`] data
+    ///
+    /// `batch_size` is the number of ADC samples per batch, see [`crate::Lockin::with_batch_size()`].
+    pub fn set_lockin(&mut self, format: Format, batch_size: usize) {
+        self.decoder.set_lockin(format, batch_size);
+    }
+
+    /// Only accept frames from these sender addresses
+    ///
+    /// An empty list accepts all senders.
+    pub fn set_senders(&mut self, senders: Vec<IpAddr>) {
+        self.allow = senders;
+    }
+
+    /// Demultiplex senders
+    ///
+    /// Each sender has its own loss statistics and traces.
+    /// The traces of all senders seen so far are returned with names prefixed
+    /// by the sender address (e.g. `10.0.0.2/ADC0`).
+    /// Only the traces of the sender of the most recent frame contain data.
+    pub fn set_demux(&mut self, demux: bool) {
+        self.demux = demux.then(Default::default);
+    }
+
+    /// Set the socket receive buffer size in bytes
+    ///
+    /// The operating system may limit the size (e.g. `net.core.rmem_max` on Linux).
+    pub fn set_recv_buffer(&mut self, size: usize) -> Result<()> {
+        self.socket.set_recv_buffer_size(size)?;
+        let actual = self.socket.recv_buffer_size()?;
+        if actual < size {
+            log::warn!("Receive buffer size limited to {} bytes", actual);
+        }
+        Ok(())
+    }
+
+    /// Set the maximum number of datagrams received per system call
+    ///
+    /// On Linux, batches are received with `recvmmsg`. Other platforms receive
+    /// one datagram at a time.
+    pub fn set_batch(&mut self, size: usize) {
+        self.batch = Batch::new(size);
+    }
+
+    /// Receive an accepted datagram
+    fn receive(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            if let Some(len) = self.pending(buf) {
+                return Ok(len);
+            }
+            self.fill()?;
+        }
+    }
+
+    /// Receive the next batch of datagrams
+    pub(super) fn fill(&mut self) -> std::io::Result<()> {
+        recv_batch(&self.socket, &mut self.batch)
+    }
+
+    /// Copy the next accepted datagram of the current batch
+    ///
+    /// The datagram metadata is kept. Datagrams from other senders are skipped.
+    pub(super) fn pending(&mut self, buf: &mut [u8]) -> Option<usize> {
+        while let Some((data, sender, timestamp)) = self.batch.next() {
+            if self.allow.is_empty() || sender.is_some_and(|s| self.allow.contains(&s.ip())) {
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                self.sender = sender;
+                self.timestamp = timestamp;
+                return Some(len);
+            }
+        }
+        None
+    }
+
+    /// A handle to the socket
+    #[cfg(feature = "async")]
+    pub(super) fn try_clone_socket(&self) -> std::io::Result<Socket> {
+        self.socket.try_clone()
+    }
+
+    /// Decode an accepted frame
+    ///
+    /// Returns whether traces were decoded.
+    pub(super) fn decode(&mut self, frame: &[u8], traces: &mut Vec<Trace>) -> Result<bool> {
+        let sender = self.sender_ip();
+        match &mut self.demux {
+            None => self.decoder.decode(frame, traces),
+            Some(demux) => {
+                let i = demux.index(sender, &self.decoder);
+                let decoded = demux.decoders[i].decode(frame, demux.union.traces(i))?;
+                if decoded {
+                    demux.union.merge(i, traces);
+                }
+                Ok(decoded)
+            }
+        }
+    }
+
+    /// Sender address of the most recent frame for demultiplexing
+    fn sender_ip(&self) -> IpAddr {
+        self.sender.map_or(Ipv4Addr::UNSPECIFIED.into(), |s| s.ip())
+    }
+
+    /// Receive a raw frame without decoding the payload
+    ///
+    /// The frame header is validated and the loss statistics are updated.
+    pub fn recv<'a>(&mut self, buf: &'a mut [u8]) -> Result<(Header, &'a [u8])> {
+        let len = self.receive(buf)?;
+        let frame = &buf[..len];
+        let (header, _) = Frame::split(frame)?;
+        let sender = self.sender_ip();
+        let decoder = match &mut self.demux {
+            None => &mut self.decoder,
+            Some(demux) => {
+                let i = demux.index(sender, &self.decoder);
+                &mut demux.decoders[i]
+            }
+        };
+        decoder.loss.update(&header);
+        Ok((header, frame))
+    }
+
+    /// Address of the sender of the most recent frame
+    pub fn sender(&self) -> Option<SocketAddr> {
+        self.sender
+    }
+
+    /// Local address the socket is bound to
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket.local_addr().ok()?.as_socket()
+    }
+}
+
+impl TraceSource for UdpSource {
+    fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()> {
+        let mut buf = [0u8; MAX_DATAGRAM];
+        loop {
+            let len = self.receive(&mut buf)?;
+            if self.decode(&buf[..len], traces)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Receive timestamp of the most recent frame
+    ///
+    /// On Linux this is the kernel receive timestamp (`SO_TIMESTAMPNS`),
+    /// on other platforms the time the packet was read from the socket.
+    fn timestamp(&self) -> Option<SystemTime> {
+        self.timestamp
+    }
+
+    fn finish(&self) {
+        match &self.demux {
+            None => self.decoder.analyze(),
+            Some(demux) => {
+                for (sender, decoder) in demux.senders.iter().zip(&demux.decoders) {
+                    log::info!("Sender {}", sender);
+                    decoder.analyze();
+                }
+            }
+        }
+    }
+}
+
+/// Enable kernel receive timestamps on a socket
+#[cfg(target_os = "linux")]
+fn enable_timestamps(socket: &Socket) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let on: libc::c_int = 1;
+    // SAFETY: valid socket and option value pointer/length
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_TIMESTAMPNS,
+            &on as *const libc::c_int as *const libc::c_void,
+            core::mem::size_of_val(&on) as _,
+        )
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn enable_timestamps(_socket: &Socket) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Sender address and receive timestamp of a received message
+///
+/// # Safety
+/// The message must have been filled in by the kernel with `name` as its address buffer.
+#[cfg(target_os = "linux")]
+unsafe fn message_info(
+    msg: &libc::msghdr,
+    name: libc::sockaddr_storage,
+) -> (Option<SocketAddr>, Option<SystemTime>) {
+    let mut timestamp = None;
+    // control messages are within the control buffer as filled by the kernel
+    let mut cmsg = libc::CMSG_FIRSTHDR(msg);
+    while !cmsg.is_null() {
+        if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_TIMESTAMPNS {
+            let ts: libc::timespec = core::ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast());
+            timestamp =
+                Some(SystemTime::UNIX_EPOCH + Duration::new(ts.tv_sec as _, ts.tv_nsec as _));
+        }
+        cmsg = libc::CMSG_NXTHDR(msg, cmsg);
+    }
+    let sender = socket2::SockAddr::new(name, msg.msg_namelen).as_socket();
+    (sender, timestamp)
+}
+
+/// Receive a batch of datagrams in one system call
+///
+/// Blocks until at least one datagram is available.
+#[cfg(target_os = "linux")]
+fn recv_batch(socket: &Socket, batch: &mut Batch) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let headers = &mut batch.headers;
+    headers.reset();
+    // SAFETY: each message points to valid name, iov and control buffers
+    let n = unsafe {
+        libc::recvmmsg(
+            socket.as_raw_fd(),
+            headers.msgs.as_mut_ptr(),
+            headers.msgs.len() as _,
+            libc::MSG_WAITFORONE as _,
+            core::ptr::null_mut(),
+        )
+    };
+    if n < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    batch.pos = 0;
+    batch.datagrams.clear();
+    for (msg, name) in headers.msgs[..n as usize].iter().zip(&headers.names) {
+        // SAFETY: the kernel filled in the message
+        let (sender, timestamp) = unsafe { message_info(&msg.msg_hdr, *name) };
+        batch
+            .datagrams
+            .push((msg.msg_len as usize, sender, timestamp));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn recv_batch(socket: &Socket, batch: &mut Batch) -> std::io::Result<()> {
+    let (len, sender, timestamp) = recv_timestamped(socket, &mut batch.buf[..MAX_DATAGRAM])?;
+    batch.pos = 0;
+    batch.datagrams.clear();
+    batch.datagrams.push((len, sender, timestamp));
+    Ok(())
+}
+
+/// Receive a datagram together with its sender and receive time
+#[cfg(not(target_os = "linux"))]
+fn recv_timestamped(
+    socket: &Socket,
+    buf: &mut [u8],
+) -> std::io::Result<(usize, Option<SocketAddr>, Option<SystemTime>)> {
+    // SAFETY: initialized bytes are valid `MaybeUninit` and recv_from only writes
+    let uninit = unsafe { &mut *(buf as *mut [u8] as *mut [core::mem::MaybeUninit<u8>]) };
+    let (len, sender) = socket.recv_from(uninit)?;
+    Ok((len, sender.as_socket(), Some(SystemTime::now())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::UdpSocket;
+
+    #[test]
+    fn timestamp() {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(1000)))
+            .unwrap();
+        socket
+            .bind(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0).into())
+            .unwrap();
+        enable_timestamps(&socket).unwrap();
+        let addr = socket.local_addr().unwrap().as_socket().unwrap();
+        let start = SystemTime::now();
+        UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .send_to(&[1, 2, 3], addr)
+            .unwrap();
+        let mut batch = Batch::new(1);
+        recv_batch(&socket, &mut batch).unwrap();
+        let (data, sender, timestamp) = batch.next().unwrap();
+        assert_eq!(data, [1, 2, 3]);
+        assert_eq!(sender.unwrap().ip(), Ipv4Addr::LOCALHOST);
+        let dt = timestamp.unwrap().duration_since(start).unwrap();
+        assert!(dt < Duration::from_secs(1));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn demux() {
+        use crate::{PayloadBuilder, ThermostatEemBuilder};
+        let mut source = UdpSource::new(Ipv4Addr::LOCALHOST, 0).unwrap();
+        let addr = source.local_addr().unwrap();
+        let a: IpAddr = Ipv4Addr::new(127, 0, 0, 2).into();
+        let b: IpAddr = Ipv4Addr::new(127, 0, 0, 3).into();
+        source.set_senders(vec![a, b]);
+        source.set_demux(true);
+        let mut frame = ThermostatEemBuilder::default();
+        frame.push([1.0; 20]);
+        for (ip, seq) in [(a, 0), (Ipv4Addr::new(127, 0, 0, 4).into(), 1), (b, 7)] {
+            UdpSocket::bind((ip, 0))
+                .unwrap()
+                .send_to(&frame.frame(seq), addr)
+                .unwrap();
+        }
+        let mut traces = vec![];
+        source.get(&mut traces).unwrap();
+        assert_eq!(traces[0].name, "127.0.0.2/T00");
+        assert_eq!(traces[0].data, [1.0]);
+        source.get(&mut traces).unwrap();
+        assert_eq!(traces.len(), 40);
+        assert!(traces[0].data.is_empty());
+        assert_eq!(traces[20].name, "127.0.0.3/T00");
+        assert_eq!(traces[20].data, [1.0]);
+        assert_eq!(source.sender().unwrap().ip(), b);
+    }
+
+    #[test]
+    fn batch() {
+        use crate::{PayloadBuilder, ThermostatEemBuilder};
+        let mut source = UdpSource::new(Ipv4Addr::LOCALHOST, 0).unwrap();
+        source.set_batch(4);
+        source.set_recv_buffer(1 << 16).unwrap();
+        let addr = source.local_addr().unwrap();
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut frame = ThermostatEemBuilder::default();
+        frame.push([1.0; 20]);
+        for seq in 0..10 {
+            socket.send_to(&frame.frame(seq), addr).unwrap();
+        }
+        let mut buf = [0; MAX_DATAGRAM];
+        for seq in 0..10 {
+            let (header, _) = source.recv(&mut buf).unwrap();
+            assert_eq!(header.seq, seq);
+            assert!(source.timestamp().is_some());
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use super::TraceSource;
+use crate::Trace;
+use anyhow::Result;
+use hound::{SampleFormat, WavReader};
+use std::{
+    fs::File,
+    io::{BufReader, ErrorKind, Read},
+    path::Path,
+};
+
+/// Whether the data starts like a WAV file
+pub fn is_wav(head: &[u8]) -> bool {
+    head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WAVE"
+}
+
+/// Traces from a PCM or float WAV file
+///
+/// Each channel is a trace named `ch0`, `ch1`, ...
+/// Integer samples are scaled to full scale (`[-1, 1)`).
+pub struct WavSource {
+    reader: WavReader<BufReader<File>>,
+    repeat: bool,
+    meta: Vec<Trace>,
+}
+
+impl core::fmt::Debug for WavSource {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // `WavReader` is not `Debug`
+        f.debug_struct("WavSource")
+            .field("spec", &self.reader.spec())
+            .field("repeat", &self.repeat)
+            .field("meta", &self.meta)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WavSource {
+    /// Open a WAV file.
+    ///
+    /// With `repeat`, wrap around at the end of the file.
+    pub fn new(path: impl AsRef<Path>, repeat: bool) -> Result<Self> {
+        let reader = WavReader::new(BufReader::with_capacity(1 << 20, File::open(path)?))?;
+        let spec = reader.spec();
+        log::info!("WAV: {:?}", spec);
+        let scale = match spec.sample_format {
+            SampleFormat::Float => 1.0,
+            SampleFormat::Int => 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32,
+        };
+        let meta = (0..spec.channels)
+            .map(|i| Trace::new(format!("ch{i}"), vec![]).with_unit("", scale))
+            .collect();
+        Ok(Self {
+            reader,
+            repeat,
+            meta,
+        })
+    }
+
+    /// Whether a file is a WAV file
+    pub fn detect(path: impl AsRef<Path>) -> Result<bool> {
+        let mut head = [0; 12];
+        let len = File::open(path)?.read(&mut head)?;
+        Ok(is_wav(&head[..len]))
+    }
+}
+
+impl TraceSource for WavSource {
+    fn get(&mut self, traces: &mut Vec<Trace>) -> Result<()> {
+        let spec = self.reader.spec();
+        let channels = spec.channels as usize;
+        loop {
+            Trace::prepare(traces, &self.meta, false);
+            // Whole sample frames only
+            let n = 1024 * channels;
+            match spec.sample_format {
+                SampleFormat::Float => {
+                    for (i, x) in self.reader.samples::<f32>().take(n).enumerate() {
+                        traces[i % channels].data.push(x?);
+                    }
+                }
+                SampleFormat::Int => {
+                    let scale = self.meta[0].scale;
+                    for (i, x) in self.reader.samples::<i32>().take(n).enumerate() {
+                        traces[i % channels].data.push(x? as f32 * scale);
+                    }
+                }
+            }
+            if !traces[0].data.is_empty() {
+                return Ok(());
+            }
+            if !self.repeat {
+                Err(std::io::Error::from(ErrorKind::UnexpectedEof))?;
+            }
+            self.reader.seek(0)?;
+        }
+    }
+
+    fn sample_rate(&self) -> Option<f64> {
+        Some(self.reader.spec().sample_rate as _)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hound::{WavSpec, WavWriter};
+
+    #[test]
+    fn channels() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wav.wav");
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut w = WavWriter::create(&path, spec).unwrap();
+        for x in [1i16 << 14, -(1 << 14), 0, i16::MIN] {
+            w.write_sample(x).unwrap();
+        }
+        w.finalize().unwrap();
+
+        assert!(WavSource::detect(&path).unwrap());
+        let mut source = WavSource::new(&path, true).unwrap();
+        assert_eq!(source.sample_rate(), Some(44100.0));
+        let mut traces = vec![];
+        for _ in 0..2 {
+            source.get(&mut traces).unwrap();
+            assert_eq!(traces[0].name, "ch0");
+            assert_eq!(traces[0].data, [0.5, 0.0]);
+            assert_eq!(traces[1].data, [-0.5, -1.0]);
+        }
+    }
+}
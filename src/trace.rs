@@ -0,0 +1,141 @@
+use std::borrow::{Borrow, Cow};
+
+/// A trace of samples with its metadata
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+    /// Trace name
+    pub name: Cow<'static, str>,
+    /// Physical unit of the values, empty if dimensionless
+    pub unit: &'static str,
+    /// Physical value of one least significant bit of the underlying raw data
+    pub scale: f32,
+    /// Sample rate divider
+    ///
+    /// The number of base sample periods per trace sample.
+    /// For a Stabilizer stream the base sample rate is the ADC sample rate.
+    pub divider: u32,
+    /// Values in physical units
+    pub data: Vec<f32>,
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Self {
+            name: Cow::Borrowed(""),
+            unit: "",
+            scale: 1.0,
+            divider: 1,
+            data: vec![],
+        }
+    }
+}
+
+impl Trace {
+    /// A dimensionless trace at the base sample rate
+    pub fn new(name: impl Into<Cow<'static, str>>, data: Vec<f32>) -> Self {
+        Self {
+            name: name.into(),
+            data,
+            ..Default::default()
+        }
+    }
+
+    /// Set physical unit and LSB scale
+    pub fn with_unit(mut self, unit: &'static str, scale: f32) -> Self {
+        self.unit = unit;
+        self.scale = scale;
+        self
+    }
+
+    /// Set the sample rate divider
+    pub fn with_divider(mut self, divider: u32) -> Self {
+        self.divider = divider;
+        self
+    }
+
+    /// Whether two traces have the same metadata
+    pub fn same_meta(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.unit == other.unit
+            && self.scale == other.scale
+            && self.divider == other.divider
+    }
+
+    /// A copy of the metadata without data
+    pub fn meta(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            unit: self.unit,
+            scale: self.scale,
+            divider: self.divider,
+            data: vec![],
+        }
+    }
+
+    /// Prepare trace buffers for reuse.
+    ///
+    /// Buffers matching the metadata of the template traces are kept,
+    /// others are replaced by the template metadata.
+    /// Surplus buffers are removed.
+    /// This does not allocate if the buffers already match the templates.
+    ///
+    /// # Args
+    /// * `traces`: Trace buffers
+    /// * `meta`: Metadata templates
+    /// * `append`: Keep the existing data, otherwise clear it
+    pub fn prepare(
+        traces: &mut Vec<Self>,
+        meta: impl IntoIterator<Item = impl Borrow<Self>>,
+        append: bool,
+    ) {
+        let mut n = 0;
+        for m in meta {
+            let m = m.borrow();
+            match traces.get_mut(n) {
+                Some(t) if t.same_meta(m) => {
+                    if !append {
+                        t.data.clear();
+                    }
+                }
+                Some(t) => *t = m.meta(),
+                None => traces.push(m.meta()),
+            }
+            n += 1;
+        }
+        traces.truncate(n);
+    }
+
+    /// Sample rate of this trace given the base sample rate
+    pub fn rate(&self, fs: f32) -> f32 {
+        fs / self.divider as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prepare() {
+        let meta = || {
+            [
+                Trace::new("a", vec![]),
+                Trace::new("b", vec![]).with_divider(2),
+            ]
+        };
+        let mut traces = vec![];
+        Trace::prepare(&mut traces, meta(), false);
+        assert_eq!(traces, meta());
+        traces[0].data.extend([1.0, 2.0]);
+        traces[1].data.push(3.0);
+        let ptr = traces[0].data.as_ptr();
+        Trace::prepare(&mut traces, meta(), true);
+        assert_eq!(traces[0].data, [1.0, 2.0]);
+        Trace::prepare(&mut traces, meta(), false);
+        assert!(traces[0].data.is_empty());
+        assert_eq!(traces[0].data.as_ptr(), ptr);
+        traces[1].data.push(3.0);
+        Trace::prepare(&mut traces, [Trace::new("b", vec![])], true);
+        assert_eq!(traces, [Trace::new("b", vec![])]);
+    }
+}
@@ -2,6 +2,7 @@ use core::f32::consts::PI;
 use derive_builder::Builder;
 
 #[derive(Debug, Builder, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Var {
     /// exponent of `pi*f*tau` in the variance frequency response (-2 for AVAR, -4 for MVAR)
     #[builder(default = "-2")]
@@ -65,4 +66,12 @@ mod test {
         println!("{}", v);
         assert!((0.13478442 - v).abs() < 1e-6);
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let var = VarBuilder::default().x_exp(-4).build().unwrap();
+        let s = serde_json::to_string(&var).unwrap();
+        assert_eq!(serde_json::from_str::<Var>(&s).unwrap(), var);
+    }
 }